@@ -0,0 +1,100 @@
+// Обращение матриц: явные формулы (2x2-4x4), LU (остальные размеры) и прежний метод Гаусса-Жордана
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use rust_3d_engine::geometry::{Matrix, Vector};
+
+// Matrix::inverse до перехода на явные формулы и LU, без изменений кроме доступа к элементам
+// через индексы: расширенная матрица [A | I] в куче. Циклы по индексам оставлены как были
+#[allow(clippy::needless_range_loop)]
+fn gauss_jordan<const N: usize>(m: &Matrix<N, N>) -> Option<Matrix<N, N>> {
+    
+    // Создаём расширенную матрицу [A | I]
+    let mut augmented: Vec<Vec<f32>> = vec![vec![0.0; 2 * N]; N];
+    
+    // Копируем исходную матрицу и добавляем единичную
+    for i in 0..N {
+        for j in 0..N {
+            augmented[i][j] = m[i][j];
+            augmented[i][j + N] = if i == j { 1.0 } else { 0.0 };
+        }
+    }
+    
+    // Приведение к ступенчатому виду (прямой ход)
+    for col in 0..N {
+        // Поиск опорного элемента (pivot)
+        let mut pivot_row = col;
+        for row in col..N {
+            if augmented[row][col].abs() > augmented[pivot_row][col].abs() {
+                pivot_row = row;
+            }
+        }
+        
+        // Если опорный элемент близок к нулю - матрица вырожденная
+        if augmented[pivot_row][col].abs() < 1e-10 {
+            return None;
+        }
+        
+        // Обмен строк, если нужно
+        if pivot_row != col {
+            augmented.swap(col, pivot_row);
+        }
+        
+        // Нормализация текущей строки
+        let pivot = augmented[col][col];
+        for j in col..(2 * N) {
+            augmented[col][j] /= pivot;
+        }
+        
+        // Обнуление элементов в текущем столбце
+        for i in 0..N {
+            if i != col {
+                let factor = augmented[i][col];
+                for j in col..(2 * N) {
+                    augmented[i][j] -= factor * augmented[col][j];
+                }
+            }
+        }
+    }
+    
+    // Извлекаем обратную матрицу из правой части
+    let mut inverse = Matrix::<N, N>::new();
+    for i in 0..N {
+        for j in 0..N {
+            inverse[i][j] = augmented[i][j + N];
+        }
+    }
+    
+    Some(inverse)
+}
+
+// хорошо обусловленная матрица без случайности, чтобы прогоны были сравнимы
+fn matrix<const N: usize>() -> Matrix<N, N> {
+    let mut m = Matrix::identity();
+    for i in 0..N {
+        for j in 0..N {
+            m[i][j] += ((i * 7 + j * 3) % 5) as f32 * 0.1;
+        }
+    }
+    m
+}
+
+fn inverse<const N: usize>(c: &mut Criterion) {
+    let m = matrix::<N>();
+    let mut group = c.benchmark_group(format!("inverse {N}x{N}"));
+    group.bench_function("Matrix::inverse", |b| b.iter(|| black_box(&m).inverse()));
+    group.bench_function("Gauss-Jordan", |b| b.iter(|| gauss_jordan(black_box(&m))));
+    group.finish();
+}
+
+fn inverse_rigid(c: &mut Criterion) {
+    let m = Matrix::rotation(Vector::new([1.0, 2.0, 3.0]), 0.7) * Matrix::translation(Vector::new([1.0, -2.0, 0.5]));
+    let mut group = c.benchmark_group("inverse rigid 4x4");
+    group.bench_function("Matrix::inverse_rigid", |b| b.iter(|| black_box(&m).inverse_rigid()));
+    group.bench_function("Matrix::inverse", |b| b.iter(|| black_box(&m).inverse()));
+    group.bench_function("Gauss-Jordan", |b| b.iter(|| gauss_jordan(black_box(&m))));
+    group.finish();
+}
+
+criterion_group!(benches, inverse::<2>, inverse::<3>, inverse::<4>, inverse::<6>, inverse_rigid);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+// Вершинная стадия через wide::f32x4 и через скалярные операторы geometry.
+// Без фичи simd сравнивать нечего, поэтому бенчмарк собирается только с ней: cargo bench --features simd
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use rust_3d_engine::{geometry::{Matrix, Vector}, simd::{self, SimdMatrix}};
+
+fn matrices() -> (Matrix<4, 4>, Matrix<4, 4>) {
+    let projection = Matrix::perspective(std::f32::consts::FRAC_PI_4, 1.0, 0.1, 100.0);
+    let model_view = Matrix::look_at(Vector::new([1.0, 1.0, 3.0]), Vector::new([0.0, 0.0, 0.0]), Vector::new([0.0, 1.0, 0.0]));
+    (projection, model_view)
+}
+
+fn vertices() -> Vec<Vector<4, f32>> {
+    (0..1024).map(|i| {
+        let t = i as f32 * 0.01;
+        Vector::new([t.sin(), t.cos(), t * 0.1, 1.0])
+    }).collect()
+}
+
+fn transform(c: &mut Criterion) {
+    let (projection, model_view) = matrices();
+    let vertices = vertices();
+    let mut group = c.benchmark_group("transform 1024 vertices");
+    group.bench_function("simd", |b| b.iter(|| {
+        let (projection, model_view) = (SimdMatrix::from(&projection), SimdMatrix::from(&model_view));
+        vertices.iter().map(|&v| simd::transform(&projection, simd::transform(&model_view, black_box(v)))).fold(0.0, |s, v| s + v.w())
+    }));
+    group.bench_function("scalar", |b| b.iter(|| {
+        vertices.iter().map(|&v| &projection * (&model_view * black_box(v))).fold(0.0, |s, v| s + v.w())
+    }));
+    group.finish();
+}
+
+fn mul(c: &mut Criterion) {
+    let (projection, model_view) = matrices();
+    let mut group = c.benchmark_group("4x4 multiply");
+    group.bench_function("simd", |b| b.iter(|| simd::mul(black_box(&projection), black_box(&model_view))));
+    group.bench_function("scalar", |b| b.iter(|| black_box(&projection) * black_box(&model_view)));
+    group.finish();
+}
+
+fn dot(c: &mut Criterion) {
+    let vertices = vertices();
+    let mut group = c.benchmark_group("dot 1024 pairs");
+    group.bench_function("simd", |b| b.iter(|| vertices.windows(2).map(|w| simd::dot(black_box(w[0]), w[1])).sum::<f32>()));
+    group.bench_function("scalar", |b| b.iter(|| vertices.windows(2).map(|w| black_box(w[0]) * w[1]).sum::<f32>()));
+    group.finish();
+}
+
+criterion_group!(benches, transform, mul, dot);
+criterion_main!(benches);
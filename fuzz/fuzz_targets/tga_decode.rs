@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_3d_engine::tga::Tga;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(image) = Tga::decode(data) {
+        assert_eq!(image.map.len(), image.width());
+        assert!(image.map.iter().all(|column| column.len() == image.height()));
+    }
+});
@@ -0,0 +1,349 @@
+use crate::{geometry::{Aabb, Ray}, model::{Model, RayHit}};
+
+const BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+// стоимость обхода узла относительно теста одного треугольника
+const TRAVERSAL_COST: f32 = 1.0;
+
+#[derive(Debug, Clone)]
+struct Node {
+    aabb: Aabb,
+    // для листа - первый треугольник, для внутреннего узла - левый потомок (правый следом)
+    first: usize,
+    count: usize,
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+/// Иерархия ограничивающих объёмов над треугольниками модели, строится по SAH.
+/// Хранит только индексы, поэтому при запросах нужна та же модель, что и при построении
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    triangles: Vec<(usize, [usize; 3])>,
+}
+
+impl Bvh {
+    pub fn build(model: &Model) -> Bvh {
+        let triangles: Vec<(usize, [usize; 3])> = model.triangles().collect();
+        let bounds: Vec<Aabb> = triangles.iter()
+            .map(|&(face, corners)| Aabb::from_points(&model.triangle(face, corners)))
+            .collect();
+
+        let mut order: Vec<usize> = (0..triangles.len()).collect();
+        let mut nodes = vec![Node { aabb: Aabb::empty(), first: 0, count: order.len() }];
+        if !order.is_empty() {
+            Bvh::subdivide(&mut nodes, 0, &mut order, &bounds);
+        }
+
+        Bvh {
+            nodes,
+            triangles: order.into_iter().map(|i| triangles[i]).collect(),
+        }
+    }
+    fn subdivide(nodes: &mut Vec<Node>, idx: usize, order: &mut [usize], bounds: &[Aabb]) {
+        let (first, count) = (nodes[idx].first, nodes[idx].count);
+        let prims = &mut order[first..first + count];
+
+        let mut aabb = Aabb::empty();
+        let mut centroids = Aabb::empty();
+        for &i in prims.iter() {
+            aabb = aabb.union(&bounds[i]);
+            centroids.extend(bounds[i].center());
+        }
+        nodes[idx].aabb = aabb;
+
+        if count <= MAX_LEAF_SIZE {
+            return;
+        }
+        let Some((axis, split)) = Bvh::find_split(prims, bounds, &centroids) else {
+            return;
+        };
+
+        // разбиение на месте: левее split - в левый узел
+        let mut left = 0;
+        for i in 0..count {
+            if bounds[prims[i]].center()[axis] < split {
+                prims.swap(i, left);
+                left += 1;
+            }
+        }
+        if left == 0 || left == count {
+            return;
+        }
+
+        let child = nodes.len();
+        nodes.push(Node { aabb: Aabb::empty(), first, count: left });
+        nodes.push(Node { aabb: Aabb::empty(), first: first + left, count: count - left });
+        nodes[idx].first = child;
+        nodes[idx].count = 0;
+
+        Bvh::subdivide(nodes, child, order, bounds);
+        Bvh::subdivide(nodes, child + 1, order, bounds);
+    }
+    /// Ищет плоскость разбиения с минимальной стоимостью SAH по корзинам вдоль каждой оси.
+    /// None если разбиение дороже листа
+    fn find_split(prims: &[usize], bounds: &[Aabb], centroids: &Aabb) -> Option<(usize, f32)> {
+        let parent_area = prims.iter().fold(Aabb::empty(), |acc, &i| acc.union(&bounds[i])).surface_area();
+        let leaf_cost = prims.len() as f32;
+        let mut best: Option<(usize, f32, f32)> = None;
+
+        for axis in 0..3 {
+            let (lo, hi) = (centroids.min[axis], centroids.max[axis]);
+            if hi - lo <= f32::EPSILON {
+                continue;
+            }
+            let scale = BINS as f32 / (hi - lo);
+            let bin_of = |c: f32| (((c - lo) * scale) as usize).min(BINS - 1);
+
+            let mut bin_bounds = [Aabb::empty(); BINS];
+            let mut bin_counts = [0usize; BINS];
+            for &i in prims {
+                let b = bin_of(bounds[i].center()[axis]);
+                bin_bounds[b] = bin_bounds[b].union(&bounds[i]);
+                bin_counts[b] += 1;
+            }
+
+            // площади и количества слева от каждой из BINS - 1 плоскостей и справа от них
+            let mut left_area = [0.0; BINS - 1];
+            let mut left_count = [0; BINS - 1];
+            let mut acc = Aabb::empty();
+            let mut n = 0;
+            for b in 0..BINS - 1 {
+                acc = acc.union(&bin_bounds[b]);
+                n += bin_counts[b];
+                left_area[b] = acc.surface_area();
+                left_count[b] = n;
+            }
+            let mut acc = Aabb::empty();
+            let mut n = 0;
+            for b in (1..BINS).rev() {
+                acc = acc.union(&bin_bounds[b]);
+                n += bin_counts[b];
+                let plane = b - 1;
+                let cost = TRAVERSAL_COST
+                    + (left_area[plane] * left_count[plane] as f32 + acc.surface_area() * n as f32) / parent_area;
+                if best.is_none_or(|(_, _, c)| cost < c) {
+                    best = Some((axis, lo + b as f32 / scale, cost));
+                }
+            }
+        }
+
+        best.filter(|&(_, _, cost)| cost < leaf_cost).map(|(axis, split, _)| (axis, split))
+    }
+    /// Ближайшее пересечение луча с моделью
+    pub fn raycast(&self, model: &Model, ray: &Ray) -> Option<RayHit> {
+        let mut nearest: Option<RayHit> = None;
+        self.traverse(ray, |first, count| {
+            for &(face, corners) in &self.triangles[first..first + count] {
+                let [a, b, c] = model.triangle(face, corners);
+                if let Some((t, bar)) = ray.intersect_triangle(a, b, c) && nearest.is_none_or(|hit| t < hit.t) {
+                    nearest = Some(RayHit { face, corners, bar, t });
+                }
+            }
+            nearest.map_or(f32::MAX, |hit| hit.t)
+        });
+        nearest
+    }
+    /// Есть ли хоть одно пересечение ближе max_t (например, для теней)
+    pub fn any_hit(&self, model: &Model, ray: &Ray, max_t: f32) -> bool {
+        let mut hit = false;
+        self.traverse(ray, |first, count| {
+            hit = self.triangles[first..first + count].iter().any(|&(face, corners)| {
+                let [a, b, c] = model.triangle(face, corners);
+                ray.intersect_triangle(a, b, c).is_some_and(|(t, _)| t < max_t)
+            });
+            if hit { -1.0 } else { max_t }
+        });
+        hit
+    }
+    /// Обход узлов, пересекаемых лучом, ближние первыми. visit получает диапазон треугольников листа
+    /// и возвращает текущую границу по t: узлы дальше неё отбрасываются, отрицательная прерывает обход
+    fn traverse(&self, ray: &Ray, mut visit: impl FnMut(usize, usize) -> f32) {
+        let mut t_max = f32::MAX;
+        if self.triangles.is_empty() || ray.intersect_aabb(&self.nodes[0].aabb).is_none() {
+            return;
+        }
+        let mut stack = vec![(0, 0.0)];
+        while let Some((idx, t_entry)) = stack.pop() {
+            // граница могла сузиться, пока узел лежал на стеке
+            if t_entry > t_max {
+                continue;
+            }
+            let node = &self.nodes[idx];
+            if node.is_leaf() {
+                t_max = visit(node.first, node.count);
+                if t_max < 0.0 {
+                    return;
+                }
+                continue;
+            }
+
+            let near = |i: usize| ray.intersect_aabb(&self.nodes[i].aabb)
+                .map(|(t, _)| (i, t))
+                .filter(|&(_, t)| t <= t_max);
+            match (near(node.first), near(node.first + 1)) {
+                (Some(l), Some(r)) => {
+                    // дальний кладётся первым, чтобы ближний снялся со стека раньше
+                    if l.1 <= r.1 {
+                        stack.push(r);
+                        stack.push(l);
+                    } else {
+                        stack.push(l);
+                        stack.push(r);
+                    }
+                },
+                (Some(child), None) | (None, Some(child)) => stack.push(child),
+                (None, None) => {},
+            }
+        }
+    }
+    /// Треугольники (номер грани, номера вершин), чьи боксы пересекают aabb
+    pub fn query_aabb(&self, model: &Model, aabb: &Aabb) -> Vec<(usize, [usize; 3])> {
+        let mut result = vec![];
+        if self.triangles.is_empty() {
+            return result;
+        }
+        let mut stack = vec![0];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if !node.aabb.intersects(aabb) {
+                continue;
+            }
+            if node.is_leaf() {
+                for &(face, corners) in &self.triangles[node.first..node.first + node.count] {
+                    if Aabb::from_points(&model.triangle(face, corners)).intersects(aabb) {
+                        result.push((face, corners));
+                    }
+                }
+            } else {
+                stack.push(node.first);
+                stack.push(node.first + 1);
+            }
+        }
+        result
+    }
+    /// Пересчитывает боксы после движения вершин без перестройки дерева.
+    /// Потомки всегда лежат в nodes дальше родителя, поэтому достаточно пройти с конца
+    pub fn refit(&mut self, model: &Model) {
+        // пустой корень не лист (count == 0), а потомков у него нет
+        if self.triangles.is_empty() {
+            return;
+        }
+        for idx in (0..self.nodes.len()).rev() {
+            let node = &self.nodes[idx];
+            let aabb = if node.is_leaf() {
+                self.triangles[node.first..node.first + node.count].iter()
+                    .fold(Aabb::empty(), |acc, &(face, corners)| acc.union(&Aabb::from_points(&model.triangle(face, corners))))
+            } else {
+                self.nodes[node.first].aabb.union(&self.nodes[node.first + 1].aabb)
+            };
+            self.nodes[idx].aabb = aabb;
+        }
+    }
+    pub fn bounds(&self) -> Aabb {
+        self.nodes[0].aabb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::geometry::Vector;
+
+    fn head() -> Model {
+        let mut model = Model::read(&Path::new(env!("CARGO_MANIFEST_DIR")).join("obj/head.obj"));
+        model.normalize_to_unit_cube();
+        model
+    }
+    fn random_point(rng: &mut StdRng, aabb: &Aabb) -> Vector<3, f32> {
+        Vector::new([0, 1, 2].map(|i| rng.random_range(aabb.min[i]..=aabb.max[i])))
+    }
+    // лучи снаружи модели в случайную точку её бокса, часть из них промахивается
+    fn random_ray(rng: &mut StdRng, aabb: &Aabb) -> Ray {
+        let mut origin = random_point(rng, aabb);
+        let axis = rng.random_range(0..3);
+        origin[axis] += if rng.random_bool(0.5) { 3.0 } else { -3.0 };
+        Ray::new(origin, (random_point(rng, aabb) - origin).normalize(1.0))
+    }
+
+    // сравнивает все запросы BVH с перебором Model::raycast и линейным проходом по боксам
+    fn assert_matches_brute_force(bvh: &Bvh, model: &Model, rng: &mut StdRng) {
+        let aabb = model.aabb();
+        assert_eq!(bvh.bounds(), aabb);
+        let mut hits = 0;
+        for _ in 0..200 {
+            let ray = random_ray(rng, &aabb);
+            let expected = model.raycast(&ray);
+            let actual = bvh.raycast(model, &ray);
+            match (expected, actual) {
+                (Some(e), Some(a)) => {
+                    // при равных t может найтись другой треугольник, поэтому сравнивается только расстояние
+                    assert!((e.t - a.t).abs() < 1e-5, "{e:?} != {a:?}");
+                    hits += 1;
+                    assert!(bvh.any_hit(model, &ray, e.t + 1e-3));
+                    assert!(!bvh.any_hit(model, &ray, e.t - 1e-3));
+                },
+                (None, None) => assert!(!bvh.any_hit(model, &ray, f32::MAX)),
+                _ => panic!("{expected:?} != {actual:?}"),
+            }
+        }
+        assert!(hits > 50, "only {hits} rays hit the model");
+
+        let triangle_bounds: Vec<_> = model.triangles()
+            .map(|(face, corners)| ((face, corners), Aabb::from_points(&model.triangle(face, corners))))
+            .collect();
+        for _ in 0..50 {
+            let (a, b) = (random_point(rng, &aabb), random_point(rng, &aabb));
+            let query = Aabb::new(a.min(b), a.max(b));
+            let mut expected: Vec<_> = triangle_bounds.iter().filter(|(_, bounds)| bounds.intersects(&query)).map(|(t, _)| *t).collect();
+            let mut actual = bvh.query_aabb(model, &query);
+            expected.sort();
+            actual.sort();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let model = head();
+        let bvh = Bvh::build(&model);
+        assert!(bvh.nodes.len() > 1);
+        assert_eq!(bvh.triangles.len(), model.triangles().count());
+        assert_matches_brute_force(&bvh, &model, &mut StdRng::seed_from_u64(37));
+    }
+
+    #[test]
+    fn matches_brute_force_after_refit() {
+        let mut model = head();
+        let mut bvh = Bvh::build(&model);
+        let mut rng = StdRng::seed_from_u64(38);
+        for v in model.verticates.iter_mut() {
+            *v = *v + Vector::new([(); 3].map(|_| rng.random_range(-0.05..0.05)));
+        }
+        bvh.refit(&model);
+        assert_matches_brute_force(&bvh, &model, &mut rng);
+    }
+
+    #[test]
+    fn empty_model() {
+        let mut model = head();
+        model.faces.clear();
+        let mut bvh = Bvh::build(&model);
+        bvh.refit(&model);
+        assert_eq!(bvh.bounds(), Aabb::empty());
+        let ray = Ray::new(Vector::new([0.0, 0.0, 5.0]), Vector::new([0.0, 0.0, -1.0]));
+        assert!(bvh.raycast(&model, &ray).is_none());
+        assert!(!bvh.any_hit(&model, &ray, f32::MAX));
+        assert!(bvh.query_aabb(&model, &model.aabb()).is_empty());
+    }
+}
+
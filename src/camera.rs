@@ -0,0 +1,271 @@
+use sdl2::{keyboard::Keycode, mouse::MouseButton};
+
+use crate::{geometry::{Matrix, Quaternion, Sphere, Vector}, input::{Binding, Bindings, InputState}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Поворот вокруг вертикали мира и горизонтальной оси камеры, горизонт не заваливается
+    Orbit,
+    /// Виртуальный шар: точка под курсором следует за мышью
+    Arcball,
+    /// Полёт: WASD - движение, Q/E - вниз/вверх, Shift - быстрее, мышь поворачивает взгляд
+    Fly,
+}
+
+/// Действия камеры, на которые назначаются клавиши и кнопки в Camera::bindings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CameraAction {
+    /// Пока зажато, движение мыши вращает камеру
+    Rotate,
+    /// Пока зажато, движение мыши сдвигает цель
+    Pan,
+    TurnLeft,
+    TurnRight,
+    TurnUp,
+    TurnDown,
+    ZoomIn,
+    ZoomOut,
+    NextMode,
+    Reset,
+    Forward,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+    Boost,
+}
+
+impl CameraAction {
+    pub fn default_bindings() -> Bindings<CameraAction> {
+        let mut bindings = Bindings::new();
+        bindings
+            .bind(CameraAction::Rotate, Binding::Button(MouseButton::Left))
+            .bind(CameraAction::Pan, Binding::Button(MouseButton::Right))
+            .bind(CameraAction::Pan, Binding::Button(MouseButton::Middle))
+            .bind(CameraAction::TurnLeft, Binding::key(Keycode::Left))
+            .bind(CameraAction::TurnRight, Binding::key(Keycode::Right))
+            .bind(CameraAction::TurnUp, Binding::key(Keycode::Up))
+            .bind(CameraAction::TurnDown, Binding::key(Keycode::Down))
+            .bind(CameraAction::ZoomIn, Binding::key(Keycode::Equals))
+            .bind(CameraAction::ZoomIn, Binding::key(Keycode::KpPlus))
+            .bind(CameraAction::ZoomOut, Binding::key(Keycode::Minus))
+            .bind(CameraAction::ZoomOut, Binding::key(Keycode::KpMinus))
+            .bind(CameraAction::NextMode, Binding::key(Keycode::Tab))
+            .bind(CameraAction::Reset, Binding::key(Keycode::Home))
+            .bind(CameraAction::Forward, Binding::key(Keycode::W))
+            .bind(CameraAction::Back, Binding::key(Keycode::S))
+            .bind(CameraAction::Left, Binding::key(Keycode::A))
+            .bind(CameraAction::Right, Binding::key(Keycode::D))
+            .bind(CameraAction::Up, Binding::key(Keycode::E))
+            .bind(CameraAction::Down, Binding::key(Keycode::Q))
+            .bind(CameraAction::Boost, Binding::key(Keycode::LShift))
+            .bind(CameraAction::Boost, Binding::key(Keycode::RShift));
+        bindings
+    }
+}
+
+/// Камера, вращающаяся вокруг точки target на расстоянии distance.
+/// Мышь: левая кнопка - вращение, правая или средняя - сдвиг, колесо - приближение.
+/// Клавиши: стрелки - вращение, +/- - приближение, Tab - смена режима, Home - исходный вид.
+/// В режиме Fly колесо и +/- меняют скорость полёта. Назначения меняются через bindings
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub mode: CameraMode,
+    pub target: Vector<3, f32>,
+    pub distance: f32,
+    /// Поворот из пространства камеры в мировое; камера смотрит вдоль своей -z
+    pub orientation: Quaternion<f32>,
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+    /// Радиан на пиксель движения мыши
+    pub rotate_speed: f32,
+    /// Радиан в секунду при зажатой стрелке
+    pub key_rotate_speed: f32,
+    /// Во сколько раз меняется distance за один щелчок колеса
+    pub zoom_step: f32,
+    /// Скорость полёта, единиц в секунду
+    pub fly_speed: f32,
+    /// Множитель скорости полёта с зажатым Shift
+    pub fly_boost: f32,
+    pub bindings: Bindings<CameraAction>,
+    width: f32,
+    height: f32,
+    home: (Vector<3, f32>, f32, Quaternion<f32>),
+}
+
+impl Camera {
+    pub fn new(target: Vector<3, f32>, distance: f32, orientation: Quaternion<f32>, fov_y: f32) -> Camera {
+        Camera {
+            mode: CameraMode::Orbit,
+            target,
+            distance,
+            orientation,
+            fov_y,
+            near: distance / 100.0,
+            far: distance * 100.0,
+            rotate_speed: 0.01,
+            key_rotate_speed: 1.5,
+            zoom_step: 1.1,
+            fly_speed: distance,
+            fly_boost: 4.0,
+            bindings: CameraAction::default_bindings(),
+            width: 1.0,
+            height: 1.0,
+            home: (target, distance, orientation),
+        }
+    }
+    /// Камера, смотрящая на сферу в направлении view_dir так, чтобы та целиком попадала в кадр
+    pub fn framing(sphere: &Sphere, view_dir: Vector<3, f32>, up: Vector<3, f32>, fov_y: f32) -> Camera {
+        let view = sphere.frame(view_dir, up, fov_y);
+        let orientation = Quaternion::from_matrix(&view.inverse_rigid());
+        let mut camera = Camera::new(sphere.center, sphere.framing_distance(fov_y), orientation, fov_y);
+        camera.near = (camera.distance - sphere.radius).max(camera.distance / 100.0) / 2.0;
+        camera.far = (camera.distance + sphere.radius) * 4.0;
+        camera.fly_speed = sphere.radius;
+        camera
+    }
+    /// Размер окна в пикселях, нужен для шара и сдвига
+    pub fn set_viewport_size(&mut self, width: usize, height: usize) {
+        self.width = width.max(1) as f32;
+        self.height = height.max(1) as f32;
+    }
+    pub fn aspect(&self) -> f32 {
+        self.width / self.height
+    }
+    pub fn eye(&self) -> Vector<3, f32> {
+        self.target + self.orientation.rotate(Vector::new([0.0, 0.0, self.distance]))
+    }
+    pub fn up(&self) -> Vector<3, f32> {
+        self.orientation.rotate(Vector::new([0.0, 1.0, 0.0]))
+    }
+    pub fn model_view(&self) -> Matrix<4, 4> {
+        Matrix::look_at(self.eye(), self.target, self.up())
+    }
+    pub fn projection(&self) -> Matrix<4, 4> {
+        Matrix::perspective(self.fov_y, self.aspect(), self.near, self.far)
+    }
+    pub fn reset(&mut self) {
+        (self.target, self.distance, self.orientation) = self.home;
+    }
+
+    /// Поворот на yaw вокруг вертикали мира и на pitch вокруг горизонтальной оси камеры
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let yaw = Quaternion::from_axis_angle(Vector::new([0.0, 1.0, 0.0]), yaw);
+        let pitch = Quaternion::from_axis_angle(Vector::new([1.0, 0.0, 0.0]), pitch);
+        self.orientation = (yaw * self.orientation * pitch).normalize();
+    }
+    /// То же, что orbit, но вращается взгляд, а глаз остаётся на месте
+    pub fn look(&mut self, yaw: f32, pitch: f32) {
+        let eye = self.eye();
+        self.orbit(yaw, pitch);
+        self.target = eye - self.orientation.rotate(Vector::new([0.0, 0.0, self.distance]));
+    }
+    /// Сдвиг глаза и цели вместе: x - вправо, y - вверх по миру, z - вперёд по взгляду
+    pub fn fly(&mut self, offset: Vector<3, f32>) {
+        let right = self.orientation.rotate(Vector::new([1.0, 0.0, 0.0]));
+        let forward = self.orientation.rotate(Vector::new([0.0, 0.0, -1.0]));
+        self.target = self.target + right * offset.x() + Vector::new([0.0, offset.y(), 0.0]) + forward * offset.z();
+    }
+    // поворот стрелками: при облёте сдвигается камера, в полёте взгляд поворачивается в сторону стрелки
+    fn rotate(&mut self, yaw: f32, pitch: f32) {
+        match self.mode {
+            CameraMode::Fly => self.look(-yaw, -pitch),
+            _ => self.orbit(yaw, pitch),
+        }
+    }
+    /// Поворот шара при перемещении курсора из from в to (пиксели окна)
+    pub fn arcball(&mut self, from: (i32, i32), to: (i32, i32)) {
+        let a = self.arcball_point(from);
+        let b = self.arcball_point(to);
+        let axis = a ^ b;
+        if axis.norm() < f32::EPSILON {
+            return;
+        }
+        let angle = (a * b).clamp(-1.0, 1.0).acos();
+        // шар поворачивается вместе с мышью, значит камера - в обратную сторону
+        let rotation = Quaternion::from_axis_angle(axis, -angle);
+        self.orientation = (self.orientation * rotation).normalize();
+    }
+    fn arcball_point(&self, (x, y): (i32, i32)) -> Vector<3, f32> {
+        let px = (2.0 * x as f32 - self.width) / self.width;
+        let py = (self.height - 2.0 * y as f32) / self.height;
+        let d = px * px + py * py;
+        if d <= 1.0 {
+            Vector::new([px, py, (1.0 - d).sqrt()])
+        } else {
+            Vector::new([px, py, 0.0]).normalize(1.0)
+        }
+    }
+    /// Сдвиг цели в плоскости экрана на (dx, dy) пикселей, точка под курсором остаётся под ним
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let units_per_pixel = 2.0 * self.distance * (self.fov_y / 2.0).tan() / self.height;
+        let right = self.orientation.rotate(Vector::new([1.0, 0.0, 0.0]));
+        self.target = self.target - right * (dx * units_per_pixel) + self.up() * (dy * units_per_pixel);
+    }
+    /// steps > 0 приближает, < 0 отдаляет; в режиме Fly меняет скорость полёта
+    pub fn zoom(&mut self, steps: f32) {
+        if self.mode == CameraMode::Fly {
+            self.fly_speed *= self.zoom_step.powf(steps);
+            return;
+        }
+        self.distance = (self.distance / self.zoom_step.powf(steps)).clamp(self.near * 2.0, self.far / 2.0);
+    }
+
+    /// Обрабатывает ввод за кадр длительностью dt секунд
+    pub fn handle_input(&mut self, input: &InputState, dt: f32) {
+        let b = &self.bindings;
+        let next_mode = b.was_pressed(input, CameraAction::NextMode);
+        let reset = b.was_pressed(input, CameraAction::Reset);
+        let zoom = input.wheel
+            + b.was_pressed(input, CameraAction::ZoomIn) as i32 as f32
+            - b.was_pressed(input, CameraAction::ZoomOut) as i32 as f32;
+        let turn = (
+            b.axis(input, CameraAction::TurnRight, CameraAction::TurnLeft),
+            b.axis(input, CameraAction::TurnDown, CameraAction::TurnUp),
+        );
+        let rotating = b.is_held(input, CameraAction::Rotate);
+        let panning = b.is_held(input, CameraAction::Pan);
+        let direction = Vector::new([
+            b.axis(input, CameraAction::Right, CameraAction::Left),
+            b.axis(input, CameraAction::Up, CameraAction::Down),
+            b.axis(input, CameraAction::Forward, CameraAction::Back),
+        ]);
+        let boost = b.is_held(input, CameraAction::Boost);
+
+        if next_mode {
+            self.mode = match self.mode {
+                CameraMode::Orbit => CameraMode::Arcball,
+                CameraMode::Arcball => CameraMode::Fly,
+                CameraMode::Fly => CameraMode::Orbit,
+            };
+        }
+        if reset {
+            self.reset();
+        }
+        if zoom != 0.0 {
+            self.zoom(zoom);
+        }
+        if turn != (0.0, 0.0) {
+            self.rotate(turn.0 * self.key_rotate_speed * dt, turn.1 * self.key_rotate_speed * dt);
+        }
+        let (dx, dy) = input.mouse_delta;
+        if (dx, dy) != (0, 0) {
+            if rotating {
+                match self.mode {
+                    CameraMode::Orbit => self.orbit(-dx as f32 * self.rotate_speed, -dy as f32 * self.rotate_speed),
+                    CameraMode::Arcball => self.arcball(input.previous_mouse(), input.mouse),
+                    CameraMode::Fly => self.look(-dx as f32 * self.rotate_speed, -dy as f32 * self.rotate_speed),
+                }
+            } else if panning {
+                self.pan(dx as f32, dy as f32);
+            }
+        }
+        if self.mode == CameraMode::Fly && direction.norm() > f32::EPSILON {
+            let speed = if boost { self.fly_speed * self.fly_boost } else { self.fly_speed };
+            // по диагонали не быстрее, чем по прямой
+            self.fly(direction.normalize(speed * dt));
+        }
+    }
+}
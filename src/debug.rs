@@ -0,0 +1,105 @@
+// Отладочная отрисовка линиями поверх сцены. Все функции принимают mvp - матрицу
+// из пространства модели в пространство отсечения камеры, и viewport кадра
+use crate::{geometry::{Aabb, Matrix, Vector}, model::Model, scene::Scene};
+
+pub const AXIS_X: u32 = 0xFF0000;
+pub const AXIS_Y: u32 = 0x00FF00;
+pub const AXIS_Z: u32 = 0x0000FF;
+pub const NORMAL: u32 = 0x00FFFF;
+pub const BOUNDS: u32 = 0xFFFF00;
+pub const LIGHT: u32 = 0xFFFFFF;
+pub const FRUSTUM: u32 = 0xFF00FF;
+
+/// Что рисовать поверх сцены
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DebugDraw {
+    /// Нормали в вершинах
+    pub normals: bool,
+    /// Длина нормалей в единицах модели
+    pub normal_length: f32,
+    /// Боксы модели и её кусков
+    pub bounds: bool,
+    /// Оси мира в начале координат
+    pub axes: bool,
+    /// Источники света и пирамиды видимости теневых источников
+    pub lights: bool,
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        DebugDraw { normals: false, normal_length: 0.05, bounds: false, axes: false, lights: false }
+    }
+}
+
+fn line(scene: &mut Scene, mvp: &Matrix<4, 4>, viewport: &Matrix<4, 4>, a: Vector<3, f32>, b: Vector<3, f32>, color: u32) {
+    scene.line(mvp * a.embed::<4>(1.0), mvp * b.embed::<4>(1.0), viewport, color, 1.0);
+}
+
+/// Нормали из Model::norm, отложенные от вершин Model::vert
+pub fn normals(scene: &mut Scene, mvp: &Matrix<4, 4>, viewport: &Matrix<4, 4>, model: &Model, length: f32, color: u32) {
+    for (iface, face) in model.faces.iter().enumerate() {
+        for nvert in 0..face.len() {
+            let p = model.vert(iface, nvert);
+            line(scene, mvp, viewport, p, p + model.norm(iface, nvert) * length, color);
+        }
+    }
+}
+
+// пары углов, отличающихся по одной оси, в нумерации Aabb::corners
+fn box_edges(corners: &[Vector<3, f32>; 8]) -> impl Iterator<Item = (Vector<3, f32>, Vector<3, f32>)> + '_ {
+    (0..8).flat_map(move |i| {
+        (0..3).filter(move |axis| i & (1 << axis) == 0).map(move |axis| (corners[i], corners[i | (1 << axis)]))
+    })
+}
+
+pub fn aabb(scene: &mut Scene, mvp: &Matrix<4, 4>, viewport: &Matrix<4, 4>, aabb: &Aabb, color: u32) {
+    if aabb.is_empty() {
+        return;
+    }
+    for (a, b) in box_edges(&aabb.corners()) {
+        line(scene, mvp, viewport, a, b, color);
+    }
+}
+
+/// Оси x, y, z длины length из точки origin
+pub fn axes(scene: &mut Scene, mvp: &Matrix<4, 4>, viewport: &Matrix<4, 4>, origin: Vector<3, f32>, length: f32) {
+    for (axis, color) in [AXIS_X, AXIS_Y, AXIS_Z].into_iter().enumerate() {
+        let mut end = origin;
+        end[axis] += length;
+        line(scene, mvp, viewport, origin, end, color);
+    }
+}
+
+/// Источник света: точка в position и, для направленного и прожектора, стрелка длины size вдоль direction
+pub fn light(scene: &mut Scene, mvp: &Matrix<4, 4>, viewport: &Matrix<4, 4>, position: Vector<3, f32>,
+             direction: Option<Vector<3, f32>>, size: f32, color: u32) {
+    scene.point(mvp * position.embed::<4>(1.0), viewport, color, 5.0);
+    let Some(direction) = direction else {
+        return;
+    };
+    let direction = direction.normalize(1.0);
+    let tip = position + direction * size;
+    line(scene, mvp, viewport, position, tip, color);
+    // наконечник из четырёх отрезков к концу стрелки
+    let helper = if direction.x().abs() < 0.9 { Vector::new([1.0, 0.0, 0.0]) } else { Vector::new([0.0, 1.0, 0.0]) };
+    let side = (direction ^ helper).normalize(size * 0.1);
+    let up = (direction ^ side).normalize(size * 0.1);
+    let base = position + direction * (size * 0.8);
+    for offset in [side, -side, up, -up] {
+        line(scene, mvp, viewport, base + offset, tip, color);
+    }
+}
+
+/// Пирамида видимости камеры или теневого источника с матрицей view_projection (мир -> отсечение)
+pub fn frustum(scene: &mut Scene, mvp: &Matrix<4, 4>, viewport: &Matrix<4, 4>, view_projection: &Matrix<4, 4>, color: u32) {
+    let Some(inverse) = view_projection.inverse() else {
+        return;
+    };
+    let corners = Aabb::new(Vector::new([-1.0, -1.0, -1.0]), Vector::new([1.0, 1.0, 1.0])).corners().map(|ndc| {
+        let p = &inverse * ndc.embed::<4>(1.0);
+        p.xyz() / p.w()
+    });
+    for (a, b) in box_edges(&corners) {
+        line(scene, mvp, viewport, a, b, color);
+    }
+}
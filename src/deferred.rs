@@ -0,0 +1,193 @@
+// Отложенное освещение: растеризация пишет в G-буфер свойства поверхности, а свет считается
+// потом, один раз на пиксель. Положения, нормали и источники - в пространстве камеры
+use crate::{geometry::Vector, postprocess::{from_rgb, to_rgb}};
+
+/// Материал пикселя, где нет непрозрачной геометрии или где её перекрыло прямое рисование
+pub const NO_MATERIAL: u16 = u16::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+    /// Свет вдоль direction
+    Directional { direction: Vector<3, f32>, color: Vector<3, f32> },
+    /// Яркость падает до нуля на расстоянии range
+    Point { position: Vector<3, f32>, color: Vector<3, f32>, range: f32 },
+}
+
+/// Параметры Блинна-Фонга, номер материала в G-буфере - индекс в Scene::materials
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    /// Доля альбедо, видимая без света
+    pub ambient: f32,
+    pub specular: f32,
+    pub shininess: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material { ambient: 0.1, specular: 0.3, shininess: 32.0 }
+    }
+}
+
+/// Свойства ближайшей поверхности в каждом пикселе; глубина - в буфере глубины сцены.
+/// Пиксель (x, y) лежит по индексу x + y * width
+#[derive(Debug, Clone, Default)]
+pub struct GBuffer {
+    pub albedo: Vec<u32>,
+    pub normal: Vec<Vector<3, f32>>,
+    pub material: Vec<u16>,
+}
+
+impl GBuffer {
+    pub fn new(width: usize, height: usize) -> GBuffer {
+        GBuffer {
+            albedo: vec![0; width * height],
+            normal: vec![Vector::empty(); width * height],
+            material: vec![NO_MATERIAL; width * height],
+        }
+    }
+    pub fn clear(&mut self) {
+        self.material.fill(NO_MATERIAL);
+    }
+    pub fn set(&mut self, index: usize, albedo: u32, normal: Vector<3, f32>, material: u16) {
+        self.albedo[index] = albedo;
+        self.normal[index] = normal;
+        self.material[index] = material;
+    }
+    /// Освещает пиксели с геометрией и отдаёт write их индекс и цвет; пиксели без материала пропускаются,
+    /// там остаётся то, что уже было в кадре. position - положение пикселя в пространстве камеры,
+    /// materials - по номерам из material. Без источников пиксели получают своё альбедо
+    pub fn light(&self, materials: &[Material], lights: &[Light], position: impl Fn(usize) -> Vector<3, f32>,
+                 mut write: impl FnMut(usize, u32)) {
+        for (index, &id) in self.material.iter().enumerate() {
+            if id == NO_MATERIAL {
+                continue;
+            }
+            let albedo = self.albedo[index];
+            if lights.is_empty() {
+                write(index, albedo);
+                continue;
+            }
+            let material = materials.get(id as usize).copied().unwrap_or_default();
+            write(index, shade(albedo, self.normal[index], position(index), &material, lights));
+        }
+    }
+}
+
+/// Цвет поверхности с альбедо albedo в точке position под всеми lights; без источников остаётся только ambient
+pub fn shade(albedo: u32, normal: Vector<3, f32>, position: Vector<3, f32>, material: &Material, lights: &[Light]) -> u32 {
+    let albedo = from_rgb(albedo);
+    // камера в начале координат
+    let view = (-position).normalize(1.0);
+    let mut result = albedo * material.ambient;
+    for light in lights {
+        let (to_light, radiance) = match *light {
+            Light::Directional { direction, color } => (-direction.normalize(1.0), color),
+            Light::Point { position: light_position, color, range } => {
+                let offset = light_position - position;
+                let distance = offset.norm();
+                let falloff = (1.0 - distance / range).max(0.0);
+                (offset / distance.max(f32::EPSILON), color * (falloff * falloff))
+            },
+        };
+        let diffuse = normal * to_light;
+        if diffuse <= 0.0 {
+            continue;
+        }
+        let half = (to_light + view).normalize(1.0);
+        let specular = (normal * half).max(0.0).powf(material.shininess) * material.specular;
+        result = result + (albedo * diffuse + Vector::new([specular; 3])).component_mul(radiance);
+    }
+    to_rgb(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: u32 = 0xFFFFFF;
+    // без ambient и блика остаётся только рассеянная часть
+    const DIFFUSE_ONLY: Material = Material { ambient: 0.0, specular: 0.0, shininess: 1.0 };
+
+    fn assert_rgb_close(actual: u32, expected: u32) {
+        for shift in [0, 8, 16] {
+            let (a, e) = ((actual >> shift & 0xFF) as i32, (expected >> shift & 0xFF) as i32);
+            assert!((a - e).abs() <= 1, "{actual:06X} != {expected:06X}");
+        }
+    }
+    fn gray(value: f32) -> u32 {
+        to_rgb(Vector::new([value; 3]))
+    }
+    // поверхность перед камерой, нормаль смотрит на неё
+    fn surface() -> (Vector<3, f32>, Vector<3, f32>) {
+        (Vector::new([0.0, 0.0, 1.0]), Vector::new([0.0, 0.0, -5.0]))
+    }
+    fn directional(direction: [f32; 3]) -> Light {
+        Light::Directional { direction: Vector::new(direction), color: Vector::new([1.0; 3]) }
+    }
+
+    #[test]
+    fn directional_follows_n_dot_l() {
+        let (normal, position) = surface();
+        assert_rgb_close(shade(WHITE, normal, position, &DIFFUSE_ONLY, &[directional([0.0, 0.0, -1.0])]), WHITE);
+        // 60 градусов к нормали
+        let angle = std::f32::consts::FRAC_PI_3;
+        let light = directional([angle.sin(), 0.0, -angle.cos()]);
+        assert_rgb_close(shade(WHITE, normal, position, &DIFFUSE_ONLY, &[light]), gray(0.5));
+        // цвет источника умножается покомпонентно
+        let red = Light::Directional { direction: Vector::new([0.0, 0.0, -1.0]), color: Vector::new([1.0, 0.0, 0.0]) };
+        assert_rgb_close(shade(WHITE, normal, position, &DIFFUSE_ONLY, &[red]), 0xFF0000);
+    }
+
+    #[test]
+    fn point_light_falls_off_with_distance() {
+        let (normal, position) = surface();
+        let at = |distance: f32| {
+            let light = Light::Point { position: position + normal * distance, color: Vector::new([1.0; 3]), range: 10.0 };
+            shade(WHITE, normal, position, &DIFFUSE_ONLY, &[light])
+        };
+        assert_rgb_close(at(2.0), gray(0.8 * 0.8));
+        assert_rgb_close(at(5.0), gray(0.5 * 0.5));
+        assert_eq!(at(10.0), 0);
+        assert_eq!(at(20.0), 0);
+    }
+
+    #[test]
+    fn back_facing_lights_add_nothing() {
+        let (normal, position) = surface();
+        let material = Material { ambient: 0.2, specular: 1.0, shininess: 8.0 };
+        let behind = [
+            directional([0.0, 0.0, 1.0]),
+            Light::Point { position: position - normal, color: Vector::new([1.0; 3]), range: 10.0 },
+        ];
+        assert_rgb_close(shade(WHITE, normal, position, &material, &behind), gray(0.2));
+    }
+
+    #[test]
+    fn no_lights_leave_only_ambient() {
+        let (normal, position) = surface();
+        let material = Material { ambient: 0.25, ..Material::default() };
+        assert_rgb_close(shade(0x804020, normal, position, &material, &[]), to_rgb(from_rgb(0x804020) * 0.25));
+    }
+
+    #[test]
+    fn empty_pixels_keep_clear_color() {
+        const CLEAR: u32 = 0x102030;
+        let (normal, position) = surface();
+        let mut gbuffer = GBuffer::new(4, 1);
+        gbuffer.set(1, WHITE, normal, 0);
+        gbuffer.set(3, WHITE, normal, 5);
+        let mut frame = [CLEAR; 4];
+        let lights = [directional([0.0, 0.0, -1.0])];
+        gbuffer.light(&[DIFFUSE_ONLY], &lights, |_| position, |index, color| frame[index] = color);
+        assert_eq!(frame[0], CLEAR);
+        assert_eq!(frame[2], CLEAR);
+        assert_rgb_close(frame[1], WHITE);
+        // материала 5 нет - берётся Material::default()
+        assert_eq!(frame[3], shade(WHITE, normal, position, &Material::default(), &lights));
+
+        gbuffer.clear();
+        let mut frame = [CLEAR; 4];
+        gbuffer.light(&[DIFFUSE_ONLY], &lights, |_| position, |index, color| frame[index] = color);
+        assert_eq!(frame, [CLEAR; 4]);
+    }
+}
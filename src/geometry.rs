@@ -1,8 +1,8 @@
-use std::{ops::{Add, BitXor, Div, Index, IndexMut, Mul, Sub}, usize};
+use std::{ops::{Add, AddAssign, BitXor, Div, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign}};
 
 use num::{Float, NumCast};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vector<const N: usize, T> {
     pub(crate) vec: [T; N]
 }
@@ -39,6 +39,9 @@ impl<const N: usize, T> Vector<N, T> {
     pub fn len(&self) -> usize{
         N
     }
+    pub fn is_empty(&self) -> bool {
+        N == 0
+    }
 }
 impl<const N: usize, T> Index<usize> for Vector<N, T> {
     type Output = T;
@@ -65,8 +68,8 @@ impl<const N: usize, T: NumCast + Copy> Vector<N, T> {
         Vector { vec }
     }
 }
-impl<const N: usize, f32> Vector<N, f32> 
-where f32: NumCast + Float{
+impl<const N: usize, T> Vector<N, T> 
+where T: NumCast + Float{
     pub fn round<U: NumCast + num::Zero + Copy>(self) -> Vector<N, U> 
     {
         let mut vec = [U::zero(); N];
@@ -125,6 +128,19 @@ where T: Sub<Output = T> + Copy + num::Zero {
     }
 }
 
+impl<const N: usize, T> Neg for Vector<N, T>
+where T: Neg<Output = T> + Copy {
+    type Output = Vector<N, T>;
+
+    fn neg(self) -> Self::Output {
+        let mut vec = self.vec;
+        for v in vec.iter_mut() {
+            *v = -*v;
+        }
+        Vector { vec }
+    }
+}
+
 impl<const N: usize, T> Mul<T> for Vector<N, T>  
 where T: Mul<Output = T> + Clone + Copy + num::Zero {
     type Output = Vector<N, T>;
@@ -170,28 +186,155 @@ where T: Div<Output = T> + Clone + Copy + num::Zero {
     }
 }
 
-impl<const N: usize, T> BitXor for Vector<N, T> 
-where T: Mul<Output = T> + Sub<Output = T> + Copy + num::Zero
-{
-    type Output = Vector<N, T>;
+impl<const N: usize, T> Vector<N, T>
+where T: PartialOrd + Copy {
+    pub fn min(self, other: Vector<N, T>) -> Vector<N, T> {
+        let mut vec = self.vec;
+        for i in 0..N {
+            if other[i] < vec[i] {
+                vec[i] = other[i];
+            }
+        }
+        Vector { vec }
+    }
+    pub fn max(self, other: Vector<N, T>) -> Vector<N, T> {
+        let mut vec = self.vec;
+        for i in 0..N {
+            if other[i] > vec[i] {
+                vec[i] = other[i];
+            }
+        }
+        Vector { vec }
+    }
+    pub fn clamp(self, lo: Vector<N, T>, hi: Vector<N, T>) -> Vector<N, T> {
+        self.max(lo).min(hi)
+    }
+}
+impl<const N: usize, T> Vector<N, T>
+where T: num::Signed + Copy {
+    pub fn abs(self) -> Vector<N, T> {
+        let mut vec = self.vec;
+        for v in vec.iter_mut() {
+            *v = v.abs();
+        }
+        Vector { vec }
+    }
+}
+impl<const N: usize, T> Vector<N, T>
+where T: num::Num + Copy {
+    /// Покомпонентное произведение
+    pub fn component_mul(self, other: Vector<N, T>) -> Vector<N, T> {
+        let mut vec = self.vec;
+        for i in 0..N {
+            vec[i] = vec[i] * other[i];
+        }
+        Vector { vec }
+    }
+    /// Покомпонентное деление
+    pub fn component_div(self, other: Vector<N, T>) -> Vector<N, T> {
+        let mut vec = self.vec;
+        for i in 0..N {
+            vec[i] = vec[i] / other[i];
+        }
+        Vector { vec }
+    }
+}
+impl<const N: usize, T: Float> Vector<N, T> {
+    pub fn lerp(self, other: Vector<N, T>, t: T) -> Vector<N, T> {
+        self + (other - self) * t
+    }
+    /// Отражение относительно поверхности с единичной нормалью n
+    pub fn reflect(self, n: Vector<N, T>) -> Vector<N, T> {
+        self - n * ((T::one() + T::one()) * (self * n))
+    }
+    /// Преломление единичного вектора через поверхность с единичной нормалью n,
+    /// eta - отношение показателей преломления. None при полном внутреннем отражении
+    pub fn refract(self, n: Vector<N, T>, eta: T) -> Option<Vector<N, T>> {
+        let cos_i = self * n;
+        let k = T::one() - eta * eta * (T::one() - cos_i * cos_i);
+        if k < T::zero() {
+            None
+        } else {
+            Some(self * eta - n * (eta * cos_i + k.sqrt()))
+        }
+    }
+}
+impl<T> Vector<3, T>
+where T: Mul<Output = T> + Sub<Output = T> + Copy {
+    pub fn cross(self, other: Vector<3, T>) -> Vector<3, T> {
+        self ^ other
+    }
+}
 
-    fn bitxor(self, other: Vector<N, T>) -> Self::Output {
-        
-        let mut vec: [T; N] = [T::zero(); N];
+macro_rules! accessors {
+    ($n:literal => $($name:ident: $i:literal),+) => {
+        impl<T: Copy> Vector<$n, T> {
+            $(
+                pub fn $name(&self) -> T {
+                    self.vec[$i]
+                }
+            )+
+        }
+    };
+}
+accessors!(2 => x: 0, y: 1);
+accessors!(3 => x: 0, y: 1, z: 2);
+accessors!(4 => x: 0, y: 1, z: 2, w: 3);
 
-        for i in 0..N {
-            let a = if i == N - 1 { 0 } else {i + 1};
-            let b = if a == 0 { 1 } else {a + 1};
-            vec[i] = other[a] * self[b] - self[b] * other[a];
+macro_rules! swizzles {
+    ($n:literal => $($name:ident: [$($i:literal),+]),+) => {
+        impl<T: Copy> Vector<$n, T> {
+            $(
+                pub fn $name(&self) -> Vector<{ [$($i),+].len() }, T> {
+                    Vector::new([$(self.vec[$i]),+])
+                }
+            )+
         }
-        
-        Vector::new(vec)
+    };
+}
+swizzles!(2 => yx: [1, 0]);
+swizzles!(3 => xy: [0, 1], yx: [1, 0], xz: [0, 2], zx: [2, 0], yz: [1, 2], zy: [2, 1],
+    xzy: [0, 2, 1], yxz: [1, 0, 2], yzx: [1, 2, 0], zxy: [2, 0, 1], zyx: [2, 1, 0]);
+swizzles!(4 => xy: [0, 1], yx: [1, 0], xz: [0, 2], zx: [2, 0], yz: [1, 2], zy: [2, 1],
+    xyz: [0, 1, 2], xzy: [0, 2, 1], yxz: [1, 0, 2], yzx: [1, 2, 0], zxy: [2, 0, 1], zyx: [2, 1, 0]);
+
+// Расширение заполняет новые компоненты нулём, сужение отбрасывает лишние
+macro_rules! resize {
+    ($($from:literal => $to:literal),+) => {
+        $(
+            impl<T: num::Zero + Copy> From<Vector<$from, T>> for Vector<$to, T> {
+                fn from(v: Vector<$from, T>) -> Vector<$to, T> {
+                    let mut vec = [T::zero(); $to];
+                    for i in 0..$from.min($to) {
+                        vec[i] = v[i];
+                    }
+                    Vector { vec }
+                }
+            }
+        )+
+    };
+}
+resize!(2 => 3, 2 => 4, 3 => 4, 3 => 2, 4 => 2, 4 => 3);
+
+/// Векторное произведение
+impl<T> BitXor for Vector<3, T> 
+where T: Mul<Output = T> + Sub<Output = T> + Copy
+{
+    type Output = Vector<3, T>;
+
+    fn bitxor(self, other: Vector<3, T>) -> Self::Output {
+        Vector::new([
+            self[1] * other[2] - self[2] * other[1],
+            self[2] * other[0] - self[0] * other[2],
+            self[0] * other[1] - self[1] * other[0]
+        ])
     }
 }
-impl<const N: usize, const MN: usize> From<Matrix<MN, 1>> for Vector<N, f32> {
-    fn from(m: Matrix<MN, 1>) -> Vector<N, f32> {
+impl<const N: usize, const MN: usize, T> From<Matrix<MN, 1, T>> for Vector<N, T>
+where T: Div<Output = T> + num::Zero + Copy {
+    fn from(m: Matrix<MN, 1, T>) -> Vector<N, T> {
         assert_eq!(N, MN - 1);
-        let mut vec: [f32; N] = [0.0; N];
+        let mut vec: [T; N] = [T::zero(); N];
 
         for i in 0..N {
             vec[i] = m[i][0]/m[N][0];
@@ -201,194 +344,1831 @@ impl<const N: usize, const MN: usize> From<Matrix<MN, 1>> for Vector<N, f32> {
     }
 }
 /////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
-#[derive(Debug, Clone)]
-pub struct Matrix<const ROWS: usize, const COLS: usize> {
-    matrix: [Vector<COLS, f32>; ROWS],
-    rows: usize,
-    cols: usize,
+const SINGULAR_EPS: f64 = 1e-10;
+
+fn singular_eps<T: Float>() -> T {
+    T::from(SINGULAR_EPS).unwrap()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix<const ROWS: usize, const COLS: usize, T = f32> {
+    matrix: [Vector<COLS, T>; ROWS],
+}
+
+impl<const ROWS: usize, const COLS: usize, T> Default for Matrix<ROWS, COLS, T>
+where T: num::Zero + Copy {
+    fn default() -> Self {
+        Matrix::new()
+    }
 }
 
-impl<const ROWS: usize, const COLS: usize> Matrix<ROWS, COLS> {
+impl<const ROWS: usize, const COLS: usize, T> Matrix<ROWS, COLS, T>
+where T: num::Zero + Copy {
     pub fn new() -> Self {
         Matrix { 
             matrix: [Vector::empty(); ROWS], 
-            rows: ROWS, 
-            cols: COLS
         }
     }
+    pub fn from_rows(matrix: [Vector<COLS, T>; ROWS]) -> Self {
+        Matrix { matrix }
+    }
     pub fn nrows(&self) -> usize {
-        self.matrix.len()
+        ROWS
     }
     pub fn ncols(&self) -> usize {
-        self.matrix[0].len()
+        COLS
     }
-    pub fn col(&self, idx: usize) -> Vector<ROWS, f32> {
+    pub fn col(&self, idx: usize) -> Vector<ROWS, T> {
         assert!(idx < COLS);
-        let mut vec: Vector<ROWS, f32> = Vector::empty();
+        let mut vec: Vector<ROWS, T> = Vector::empty();
         for i in 0..ROWS {
             vec[i] = self[i][idx];
         }
         vec
     }
-    pub fn trunspose(&self) -> Matrix<COLS, ROWS> {
-        let mut m: Matrix<COLS, ROWS> = Matrix::new();
+    pub fn transpose(&self) -> Matrix<COLS, ROWS, T> {
+        let mut m: Matrix<COLS, ROWS, T> = Matrix::new();
         for i in  0..COLS {
             m[i] = self.col(i);
         }
         m
     }
     
-    pub fn set_col(&mut self, idx: usize, v: Vector<ROWS, f32>) {
+    pub fn set_col(&mut self, idx: usize, v: Vector<ROWS, T>) {
         assert!(idx < COLS);
-        for i in ROWS..0 {
+        for i in 0..ROWS {
             self[i][idx] = v[i];
         }
     }
+    pub fn cast<U: NumCast + num::Zero + Copy>(&self) -> Matrix<ROWS, COLS, U>
+    where T: NumCast {
+        let mut m: Matrix<ROWS, COLS, U> = Matrix::new();
+        for i in 0..ROWS {
+            m[i] = self[i].cast();
+        }
+        m
+    }
 }
-impl<const N: usize> Matrix<N, N> {
-    /// Вычисляет обратную матрицу методом Гаусса-Жордана
-    /// Возвращает Option<Matrix<N, N>> - None если матрица вырожденная
-    pub fn inverse(&self) -> Option<Matrix<N, N>> {
-        
-        // Создаём расширенную матрицу [A | I]
-        let mut augmented: Vec<Vec<f32>> = vec![vec![0.0; 2 * N]; N];
-        
-        // Копируем исходную матрицу и добавляем единичную
+impl<const N: usize, T> Matrix<N, N, T>
+where T: num::Zero + num::One + Copy {
+    pub fn identity() -> Matrix<N, N, T> {
+        let mut m: Matrix<N, N, T> = Matrix::new();
         for i in 0..N {
-            for j in 0..N {
-                augmented[i][j] = self.matrix[i][j];
-                augmented[i][j + N] = if i == j { 1.0 } else { 0.0 };
-            }
+            m[i][i] = T::one();
         }
-        
-        // Приведение к ступенчатому виду (прямой ход)
+
+        m
+    }
+}
+impl<const N: usize, T: Float> Matrix<N, N, T> {
+    /// Вычисляет обратную матрицу: для 2x2, 3x3 и 4x4 явными формулами без аллокаций,
+    /// для остальных размеров и почти вырожденных на вид матриц через LU-разложение.
+    /// Возвращает Option<Matrix<N, N>> - None если матрица вырожденная
+    pub fn inverse(&self) -> Option<Matrix<N, N, T>> {
+        let m = self;
+        let mut inv: Matrix<N, N, T> = Matrix::new();
+
+        match N {
+            2 => {
+                let det = m.determinant();
+                if m.is_singular(det) {
+                    return m.lu().map(|lu| lu.inverse());
+                }
+                inv[0][0] = m[1][1] / det;
+                inv[0][1] = -m[0][1] / det;
+                inv[1][0] = -m[1][0] / det;
+                inv[1][1] = m[0][0] / det;
+            },
+            3 => {
+                let det = m.determinant();
+                if m.is_singular(det) {
+                    return m.lu().map(|lu| lu.inverse());
+                }
+                for i in 0..3 {
+                    for j in 0..3 {
+                        // присоединённая матрица: inv[i][j] = cofactor(j, i) / det
+                        let (r0, r1) = ((j + 1) % 3, (j + 2) % 3);
+                        let (c0, c1) = ((i + 1) % 3, (i + 2) % 3);
+                        inv[i][j] = (m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]) / det;
+                    }
+                }
+            },
+            4 => {
+                // разложение Лапласа по парам строк 0-1 и 2-3
+                let s0 = m[0][0] * m[1][1] - m[1][0] * m[0][1];
+                let s1 = m[0][0] * m[1][2] - m[1][0] * m[0][2];
+                let s2 = m[0][0] * m[1][3] - m[1][0] * m[0][3];
+                let s3 = m[0][1] * m[1][2] - m[1][1] * m[0][2];
+                let s4 = m[0][1] * m[1][3] - m[1][1] * m[0][3];
+                let s5 = m[0][2] * m[1][3] - m[1][2] * m[0][3];
+
+                let c5 = m[2][2] * m[3][3] - m[3][2] * m[2][3];
+                let c4 = m[2][1] * m[3][3] - m[3][1] * m[2][3];
+                let c3 = m[2][1] * m[3][2] - m[3][1] * m[2][2];
+                let c2 = m[2][0] * m[3][3] - m[3][0] * m[2][3];
+                let c1 = m[2][0] * m[3][2] - m[3][0] * m[2][2];
+                let c0 = m[2][0] * m[3][1] - m[3][0] * m[2][1];
+
+                let det = s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0;
+                if m.is_singular(det) {
+                    return m.lu().map(|lu| lu.inverse());
+                }
+                let d = T::one() / det;
+
+                inv[0][0] = ( m[1][1] * c5 - m[1][2] * c4 + m[1][3] * c3) * d;
+                inv[0][1] = (-m[0][1] * c5 + m[0][2] * c4 - m[0][3] * c3) * d;
+                inv[0][2] = ( m[3][1] * s5 - m[3][2] * s4 + m[3][3] * s3) * d;
+                inv[0][3] = (-m[2][1] * s5 + m[2][2] * s4 - m[2][3] * s3) * d;
+
+                inv[1][0] = (-m[1][0] * c5 + m[1][2] * c2 - m[1][3] * c1) * d;
+                inv[1][1] = ( m[0][0] * c5 - m[0][2] * c2 + m[0][3] * c1) * d;
+                inv[1][2] = (-m[3][0] * s5 + m[3][2] * s2 - m[3][3] * s1) * d;
+                inv[1][3] = ( m[2][0] * s5 - m[2][2] * s2 + m[2][3] * s1) * d;
+
+                inv[2][0] = ( m[1][0] * c4 - m[1][1] * c2 + m[1][3] * c0) * d;
+                inv[2][1] = (-m[0][0] * c4 + m[0][1] * c2 - m[0][3] * c0) * d;
+                inv[2][2] = ( m[3][0] * s4 - m[3][1] * s2 + m[3][3] * s0) * d;
+                inv[2][3] = (-m[2][0] * s4 + m[2][1] * s2 - m[2][3] * s0) * d;
+
+                inv[3][0] = (-m[1][0] * c3 + m[1][1] * c1 - m[1][2] * c0) * d;
+                inv[3][1] = ( m[0][0] * c3 - m[0][1] * c1 + m[0][2] * c0) * d;
+                inv[3][2] = (-m[3][0] * s3 + m[3][1] * s1 - m[3][2] * s0) * d;
+                inv[3][3] = ( m[2][0] * s3 - m[2][1] * s1 + m[2][2] * s0) * d;
+            },
+            _ => return self.lu().map(|lu| lu.inverse()),
+        }
+
+        Some(inv)
+    }
+    // |det| не больше произведения длин строк (неравенство Адамара), так что сравнение с ним
+    // не зависит от масштаба. Перенос удлиняет строки, и у translation * scaling(1e-3) отношение
+    // всё равно мало - такие матрицы inverse досчитывает через LU с выбором ведущего элемента
+    fn is_singular(&self, det: T) -> bool {
+        let volume = self.matrix.iter().fold(T::one(), |acc, row| acc * row.vec.iter().fold(T::zero(), |sum, &x| sum + x * x).sqrt());
+        det.abs() <= singular_eps::<T>() * volume
+    }
+    pub fn determinant(&self) -> T {
+        let m = self;
+        match N {
+            0 => T::one(),
+            1 => m[0][0],
+            2 => m[0][0] * m[1][1] - m[0][1] * m[1][0],
+            3 => m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+               - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+               + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]),
+            _ => self.lu().map_or(T::zero(), |lu| lu.determinant()),
+        }
+    }
+    /// LU-разложение с частичным выбором ведущего элемента: P * A = L * U.
+    /// None если матрица вырожденная
+    pub fn lu(&self) -> Option<Lu<N, T>> {
+        let mut lu = self.clone();
+        let mut perm = [0; N];
+        for (i, p) in perm.iter_mut().enumerate() {
+            *p = i;
+        }
+        let mut sign = T::one();
+        // порог для опорного элемента относительно самого большого элемента матрицы
+        let scale = self.matrix.iter().flat_map(|row| row.vec.iter()).fold(T::zero(), |acc, &x| acc.max(x.abs()));
+
         for col in 0..N {
-            // Поиск опорного элемента (pivot)
             let mut pivot_row = col;
-            for row in col..N {
-                if augmented[row][col].abs() > augmented[pivot_row][col].abs() {
+            for row in col + 1..N {
+                if lu[row][col].abs() > lu[pivot_row][col].abs() {
                     pivot_row = row;
                 }
             }
-            
-            // Если опорный элемент близок к нулю - матрица вырожденная
-            if augmented[pivot_row][col].abs() < 1e-10 {
+            if lu[pivot_row][col].abs() <= singular_eps::<T>() * scale {
                 return None;
             }
-            
-            // Обмен строк, если нужно
             if pivot_row != col {
-                augmented.swap(col, pivot_row);
-            }
-            
-            // Нормализация текущей строки
-            let pivot = augmented[col][col];
-            for j in col..(2 * N) {
-                augmented[col][j] /= pivot;
+                lu.matrix.swap(col, pivot_row);
+                perm.swap(col, pivot_row);
+                sign = -sign;
             }
-            
-            // Обнуление элементов в текущем столбце
-            for i in 0..N {
-                if i != col {
-                    let factor = augmented[i][col];
-                    for j in col..(2 * N) {
-                        augmented[i][j] -= factor * augmented[col][j];
-                    }
+
+            let pivot = lu[col][col];
+            for row in col + 1..N {
+                let factor = lu[row][col] / pivot;
+                lu[row][col] = factor;
+                for j in col + 1..N {
+                    lu[row][j] = lu[row][j] - factor * lu[col][j];
                 }
             }
         }
-        
-        // Извлекаем обратную матрицу из правой части
-        let mut inverse = Matrix::<N, N>::new();
+
+        Some(Lu { lu, perm, sign })
+    }
+    /// Решает систему A * x = b, None если матрица вырожденная
+    pub fn solve(&self, b: Vector<N, T>) -> Option<Vector<N, T>> {
+        self.lu().map(|lu| lu.solve(b))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Lu<const N: usize, T = f32> {
+    lu: Matrix<N, N, T>,
+    perm: [usize; N],
+    sign: T,
+}
+
+impl<const N: usize, T: Float> Lu<N, T> {
+    pub fn determinant(&self) -> T {
+        let mut det = self.sign;
         for i in 0..N {
-            for j in 0..N {
-                inverse.matrix[i][j] = augmented[i][j + N];
+            det = det * self.lu[i][i];
+        }
+        det
+    }
+    pub fn solve(&self, b: Vector<N, T>) -> Vector<N, T> {
+        let mut x: Vector<N, T> = Vector::empty();
+        // прямой ход: L * y = P * b
+        for i in 0..N {
+            let mut sum = b[self.perm[i]];
+            for j in 0..i {
+                sum = sum - self.lu[i][j] * x[j];
             }
+            x[i] = sum;
         }
-        
-        Some(inverse)
+        // обратный ход: U * x = y
+        for i in (0..N).rev() {
+            let mut sum = x[i];
+            for j in i + 1..N {
+                sum = sum - self.lu[i][j] * x[j];
+            }
+            x[i] = sum / self.lu[i][i];
+        }
+        x
+    }
+    pub fn inverse(&self) -> Matrix<N, N, T> {
+        let mut inv: Matrix<N, N, T> = Matrix::new();
+        for j in 0..N {
+            let mut e: Vector<N, T> = Vector::empty();
+            e[j] = T::one();
+            let col = self.solve(e);
+            for i in 0..N {
+                inv[i][j] = col[i];
+            }
+        }
+        inv
     }
+}
 
-    pub fn identity() -> Matrix<N, N> {
-        let mut m: Matrix<N, N> = Matrix::new();
-        for i in 0..N {
-            for j in 0..N  {
-                m[i][j] = if i == j { 1.0 } else { 0.0 }
+impl<T: Float> Matrix<4, 4, T> {
+    /// Обратная для жёсткого преобразования (поворот + перенос): R^T и -R^T * t
+    pub fn inverse_rigid(&self) -> Matrix<4, 4, T> {
+        let mut m: Matrix<4, 4, T> = Matrix::identity();
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] = self[j][i];
             }
         }
+        for i in 0..3 {
+            m[i][3] = -(m[i][0] * self[0][3] + m[i][1] * self[1][3] + m[i][2] * self[2][3]);
+        }
+        m
+    }
+    pub fn translation(v: Vector<3, T>) -> Matrix<4, 4, T> {
+        let mut m: Matrix<4, 4, T> = Matrix::identity();
+        for i in 0..3 {
+            m[i][3] = v[i];
+        }
+        m
+    }
+    pub fn scaling(v: Vector<3, T>) -> Matrix<4, 4, T> {
+        let mut m: Matrix<4, 4, T> = Matrix::identity();
+        for i in 0..3 {
+            m[i][i] = v[i];
+        }
+        m
+    }
+    pub fn rotation_x(angle: T) -> Matrix<4, 4, T> {
+        let (sin, cos) = angle.sin_cos();
+        let mut m: Matrix<4, 4, T> = Matrix::identity();
+        m[1][1] = cos;
+        m[1][2] = -sin;
+        m[2][1] = sin;
+        m[2][2] = cos;
+        m
+    }
+    pub fn rotation_y(angle: T) -> Matrix<4, 4, T> {
+        let (sin, cos) = angle.sin_cos();
+        let mut m: Matrix<4, 4, T> = Matrix::identity();
+        m[0][0] = cos;
+        m[0][2] = sin;
+        m[2][0] = -sin;
+        m[2][2] = cos;
+        m
+    }
+    pub fn rotation_z(angle: T) -> Matrix<4, 4, T> {
+        let (sin, cos) = angle.sin_cos();
+        let mut m: Matrix<4, 4, T> = Matrix::identity();
+        m[0][0] = cos;
+        m[0][1] = -sin;
+        m[1][0] = sin;
+        m[1][1] = cos;
+        m
+    }
+    /// Поворот на угол angle (радианы) вокруг произвольной оси, против часовой стрелки
+    pub fn rotation(axis: Vector<3, T>, angle: T) -> Matrix<4, 4, T> {
+        Quaternion::from_axis_angle(axis, angle).to_matrix()
+    }
+    /// Перспективная проекция в стиле OpenGL: камера смотрит вдоль -z, глубина near..far переходит в -1..1
+    pub fn perspective(fov_y: T, aspect: T, near: T, far: T) -> Matrix<4, 4, T> {
+        let two = T::one() + T::one();
+        let f = T::one() / (fov_y / two).tan();
+        let mut m: Matrix<4, 4, T> = Matrix::new();
+        m[0][0] = f / aspect;
+        m[1][1] = f;
+        m[2][2] = (far + near) / (near - far);
+        m[2][3] = two * far * near / (near - far);
+        m[3][2] = -T::one();
+        m
+    }
+    pub fn orthographic(left: T, right: T, bottom: T, top: T, near: T, far: T) -> Matrix<4, 4, T> {
+        let two = T::one() + T::one();
+        let mut m: Matrix<4, 4, T> = Matrix::identity();
+        m[0][0] = two / (right - left);
+        m[1][1] = two / (top - bottom);
+        m[2][2] = -two / (far - near);
+        m[0][3] = -(right + left) / (right - left);
+        m[1][3] = -(top + bottom) / (top - bottom);
+        m[2][3] = -(far + near) / (far - near);
+        m
+    }
+    pub fn look_at(eye: Vector<3, T>, center: Vector<3, T>, up: Vector<3, T>) -> Matrix<4, 4, T> {
+        let z = (eye - center).normalize(T::one());
+        let x = (up ^ z).normalize(T::one());
+        let y = z ^ x;
+
+        let mut m: Matrix<4, 4, T> = Matrix::identity();
+        for i in 0..3 {
+            m[0][i] = x[i];
+            m[1][i] = y[i];
+            m[2][i] = z[i];
+        }
+        m[0][3] = -(x * eye);
+        m[1][3] = -(y * eye);
+        m[2][3] = -(z * eye);
+        m
+    }
+    /// Переводит NDC (-1..1) в экранные координаты окна [x, x + w] x [y, y + h] и глубину 0..depth
+    pub fn viewport(x: T, y: T, w: T, h: T, depth: T) -> Matrix<4, 4, T> {
+        let two = T::one() + T::one();
+        let mut m: Matrix<4, 4, T> = Matrix::identity();
+        m[0][3] = x + w / two;
+        m[1][3] = y + h / two;
+        m[2][3] = depth / two;
+
+        m[0][0] = w / two;
+        m[1][1] = h / two;
+        m[2][2] = depth / two;
 
         m
     }
 }
 
 
-impl<const ROWS: usize, const COLS: usize>  
-Index<usize> for Matrix<ROWS, COLS> {
-    type Output = Vector<COLS, f32>;
+impl<const ROWS: usize, const COLS: usize, T>  
+Index<usize> for Matrix<ROWS, COLS, T> {
+    type Output = Vector<COLS, T>;
 
     fn index(&self, index: usize) -> &Self::Output {
         &self.matrix[index]
     }
 }
 
-impl<const ROWS: usize, const COLS: usize>  
-IndexMut<usize> for Matrix<ROWS, COLS> {
+impl<const ROWS: usize, const COLS: usize, T>  
+IndexMut<usize> for Matrix<ROWS, COLS, T> {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
         &mut self.matrix[index]
     }
 }
 
-impl<const ROWS: usize, const COLS: usize, const N: usize> 
-Mul<Matrix<COLS, N>> for Matrix<ROWS, COLS> 
+impl<const ROWS: usize, const COLS: usize, const N: usize, T> 
+Mul<&Matrix<COLS, N, T>> for &Matrix<ROWS, COLS, T> 
+where T: Add<Output = T> + Mul<Output = T> + num::Zero + Copy
 {
-    type Output = Matrix<COLS, N>;
+    type Output = Matrix<ROWS, N, T>;
 
-    fn mul(self, other: Matrix<COLS, N>) -> Self::Output {
-        assert_eq!(self.cols, other.rows);
+    fn mul(self, other: &Matrix<COLS, N, T>) -> Self::Output {
         let mut m = Matrix::new();
         for i in 0..ROWS {
             for j in 0..N {
-                m[i][j] = 0.0;
+                let mut sum = T::zero();
 
                 for k in 0..COLS {
-                    m[i][j] += self[i][k] * other[k][j];
+                    sum = sum + self[i][k] * other[k][j];
                 }
+                m[i][j] = sum;
             }
         }
         m
     }
 }
-impl<const ROWS: usize, const COLS: usize> 
-    Mul<Vector<COLS, f32>> for Matrix<ROWS, COLS> 
+impl<const ROWS: usize, const COLS: usize, const N: usize, T> 
+Mul<Matrix<COLS, N, T>> for Matrix<ROWS, COLS, T> 
+where T: Add<Output = T> + Mul<Output = T> + num::Zero + Copy
+{
+    type Output = Matrix<ROWS, N, T>;
+
+    fn mul(self, other: Matrix<COLS, N, T>) -> Self::Output {
+        &self * &other
+    }
+}
+impl<const ROWS: usize, const COLS: usize, T> 
+    Mul<Vector<COLS, T>> for &Matrix<ROWS, COLS, T> 
+where T: Add<Output = T> + Mul<Output = T> + num::Zero + Copy
 {
-    type Output = Vector<ROWS, f32>;
+    type Output = Vector<ROWS, T>;
 
-    fn mul(self, vec: Vector<COLS, f32>) -> Self::Output {
-        let mut result = Vector::<ROWS, f32>::empty(); // или new()
+    fn mul(self, vec: Vector<COLS, T>) -> Self::Output {
+        let mut result = Vector::<ROWS, T>::empty();
         
         for i in 0..ROWS {  // Для каждой строки матрицы
-            let mut sum = 0.0;
-            
-            for j in 0..COLS {  // Для каждого элемента в строке
-                sum += self[i][j] * vec.vec[j];
-            }
-            
-            result.vec[i] = sum;
+            result.vec[i] = self[i] * vec;
         }
         
         result
     }
 }
+impl<const ROWS: usize, const COLS: usize, T> 
+    Mul<Vector<COLS, T>> for Matrix<ROWS, COLS, T> 
+where T: Add<Output = T> + Mul<Output = T> + num::Zero + Copy
+{
+    type Output = Vector<ROWS, T>;
+
+    fn mul(self, vec: Vector<COLS, T>) -> Self::Output {
+        &self * vec
+    }
+}
+impl<const ROWS: usize, const COLS: usize, T> 
+    Mul<T> for Matrix<ROWS, COLS, T> 
+where T: Mul<Output = T> + num::Zero + Copy
+{
+    type Output = Matrix<ROWS, COLS, T>;
+
+    fn mul(mut self, k: T) -> Self::Output {
+        self *= k;
+        self
+    }
+}
+impl<const ROWS: usize, const COLS: usize, T> 
+    MulAssign<T> for Matrix<ROWS, COLS, T> 
+where T: Mul<Output = T> + num::Zero + Copy
+{
+    fn mul_assign(&mut self, k: T) {
+        for row in self.matrix.iter_mut() {
+            *row = *row * k;
+        }
+    }
+}
+impl<const ROWS: usize, const COLS: usize, T> 
+    Add for Matrix<ROWS, COLS, T> 
+where T: Add<Output = T> + num::Zero + Copy
+{
+    type Output = Matrix<ROWS, COLS, T>;
+
+    fn add(mut self, other: Matrix<ROWS, COLS, T>) -> Self::Output {
+        self += &other;
+        self
+    }
+}
+impl<const ROWS: usize, const COLS: usize, T> 
+    AddAssign<&Matrix<ROWS, COLS, T>> for Matrix<ROWS, COLS, T> 
+where T: Add<Output = T> + num::Zero + Copy
+{
+    fn add_assign(&mut self, other: &Matrix<ROWS, COLS, T>) {
+        for i in 0..ROWS {
+            self[i] = self[i] + other[i];
+        }
+    }
+}
+impl<const ROWS: usize, const COLS: usize, T> 
+    AddAssign for Matrix<ROWS, COLS, T> 
+where T: Add<Output = T> + num::Zero + Copy
+{
+    fn add_assign(&mut self, other: Matrix<ROWS, COLS, T>) {
+        *self += &other;
+    }
+}
+impl<const ROWS: usize, const COLS: usize, T> 
+    Sub for Matrix<ROWS, COLS, T> 
+where T: Sub<Output = T> + num::Zero + Copy
+{
+    type Output = Matrix<ROWS, COLS, T>;
+
+    fn sub(mut self, other: Matrix<ROWS, COLS, T>) -> Self::Output {
+        self -= &other;
+        self
+    }
+}
+impl<const ROWS: usize, const COLS: usize, T> 
+    SubAssign<&Matrix<ROWS, COLS, T>> for Matrix<ROWS, COLS, T> 
+where T: Sub<Output = T> + num::Zero + Copy
+{
+    fn sub_assign(&mut self, other: &Matrix<ROWS, COLS, T>) {
+        for i in 0..ROWS {
+            self[i] = self[i] - other[i];
+        }
+    }
+}
+impl<const ROWS: usize, const COLS: usize, T> 
+    SubAssign for Matrix<ROWS, COLS, T> 
+where T: Sub<Output = T> + num::Zero + Copy
+{
+    fn sub_assign(&mut self, other: Matrix<ROWS, COLS, T>) {
+        *self -= &other;
+    }
+}
+impl<const ROWS: usize, const COLS: usize, T> 
+    Neg for Matrix<ROWS, COLS, T> 
+where T: Neg<Output = T> + Copy
+{
+    type Output = Matrix<ROWS, COLS, T>;
 
-impl<const N: usize, const VN: usize> From<Vector<VN, f32>> for Matrix<N, 1> {
-    fn from(v: Vector<VN, f32>) -> Matrix<N, 1> 
+    fn neg(mut self) -> Self::Output {
+        for row in self.matrix.iter_mut() {
+            for v in row.vec.iter_mut() {
+                *v = -*v;
+            }
+        }
+        self
+    }
+}
+
+impl<const N: usize, const VN: usize, T> From<Vector<VN, T>> for Matrix<N, 1, T>
+where T: num::Zero + num::One + Copy {
+    fn from(v: Vector<VN, T>) -> Matrix<N, 1, T> 
     {
         assert_eq!(N, VN + 1);
-        let mut m: Matrix<N, 1> = Matrix::new();
+        let mut m: Matrix<N, 1, T> = Matrix::new();
         for i in 0..VN {
             m[i][0] = v[i];
         }
-        m[VN][0] = 1.0;
+        m[VN][0] = T::one();
+        m
+    }
+}
+/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion<T> {
+    pub w: T,
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+impl<T: Float> Quaternion<T> {
+    pub fn new(w: T, x: T, y: T, z: T) -> Self {
+        Quaternion { w, x, y, z }
+    }
+    pub fn identity() -> Self {
+        Quaternion::new(T::one(), T::zero(), T::zero(), T::zero())
+    }
+    pub fn from_axis_angle(axis: Vector<3, T>, angle: T) -> Self {
+        let half = angle / (T::one() + T::one());
+        let axis = axis.normalize(half.sin());
+        Quaternion::new(half.cos(), axis[0], axis[1], axis[2])
+    }
+    /// Возвращает ось и угол поворота; для единичного кватерниона ось (1, 0, 0)
+    pub fn to_axis_angle(self) -> (Vector<3, T>, T) {
+        let q = self.normalize();
+        let w = q.w.max(-T::one()).min(T::one());
+        let s = (T::one() - w * w).sqrt();
+        let angle = (T::one() + T::one()) * w.acos();
+
+        if s < T::epsilon() {
+            (Vector::new([T::one(), T::zero(), T::zero()]), angle)
+        } else {
+            (Vector::new([q.x / s, q.y / s, q.z / s]), angle)
+        }
+    }
+    /// Углы Эйлера в радианах: сначала поворот вокруг X, затем Y, затем Z (q = qz * qy * qx)
+    pub fn from_euler(x: T, y: T, z: T) -> Self {
+        let two = T::one() + T::one();
+        let (sx, cx) = (x / two).sin_cos();
+        let (sy, cy) = (y / two).sin_cos();
+        let (sz, cz) = (z / two).sin_cos();
+
+        Quaternion::new(
+            cx * cy * cz + sx * sy * sz,
+            sx * cy * cz - cx * sy * sz,
+            cx * sy * cz + sx * cy * sz,
+            cx * cy * sz - sx * sy * cz,
+        )
+    }
+    /// Обратное к from_euler, возвращает (x, y, z)
+    pub fn to_euler(self) -> (T, T, T) {
+        let q = self.normalize();
+        let two = T::one() + T::one();
+
+        let x = (two * (q.w * q.x + q.y * q.z)).atan2(T::one() - two * (q.x * q.x + q.y * q.y));
+        let sin_y = (two * (q.w * q.y - q.z * q.x)).max(-T::one()).min(T::one());
+        let y = sin_y.asin();
+        let z = (two * (q.w * q.z + q.x * q.y)).atan2(T::one() - two * (q.y * q.y + q.z * q.z));
+
+        (x, y, z)
+    }
+    pub fn dot(&self, other: &Quaternion<T>) -> T {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+    pub fn norm(&self) -> T {
+        self.dot(self).sqrt()
+    }
+    pub fn normalize(&self) -> Self {
+        let n = self.norm();
+        Quaternion::new(self.w / n, self.x / n, self.y / n, self.z / n)
+    }
+    pub fn conjugate(&self) -> Self {
+        Quaternion::new(self.w, -self.x, -self.y, -self.z)
+    }
+    pub fn inverse(&self) -> Self {
+        let n = self.dot(self);
+        let c = self.conjugate();
+        Quaternion::new(c.w / n, c.x / n, c.y / n, c.z / n)
+    }
+    pub fn rotate(&self, v: Vector<3, T>) -> Vector<3, T> {
+        let two = T::one() + T::one();
+        // t = 2 * (q.xyz x v), v' = v + w * t + q.xyz x t
+        let t = [
+            two * (self.y * v[2] - self.z * v[1]),
+            two * (self.z * v[0] - self.x * v[2]),
+            two * (self.x * v[1] - self.y * v[0]),
+        ];
+        Vector::new([
+            v[0] + self.w * t[0] + (self.y * t[2] - self.z * t[1]),
+            v[1] + self.w * t[1] + (self.z * t[0] - self.x * t[2]),
+            v[2] + self.w * t[2] + (self.x * t[1] - self.y * t[0]),
+        ])
+    }
+    /// Нормализованная линейная интерполяция, идёт по короткой дуге
+    pub fn nlerp(&self, other: &Quaternion<T>, t: T) -> Self {
+        let other = if self.dot(other) < T::zero() { -*other } else { *other };
+        let s = T::one() - t;
+        Quaternion::new(
+            self.w * s + other.w * t,
+            self.x * s + other.x * t,
+            self.y * s + other.y * t,
+            self.z * s + other.z * t,
+        ).normalize()
+    }
+    /// Сферическая линейная интерполяция, идёт по короткой дуге
+    pub fn slerp(&self, other: &Quaternion<T>, t: T) -> Self {
+        let mut cos = self.dot(other);
+        let other = if cos < T::zero() {
+            cos = -cos;
+            -*other
+        } else {
+            *other
+        };
+
+        // почти совпадающие повороты: sin(theta) ~ 0, хватает nlerp
+        if cos > T::one() - T::epsilon().sqrt() {
+            return self.nlerp(&other, t);
+        }
+
+        let theta = cos.acos();
+        let sin = theta.sin();
+        let a = ((T::one() - t) * theta).sin() / sin;
+        let b = (t * theta).sin() / sin;
+        Quaternion::new(
+            self.w * a + other.w * b,
+            self.x * a + other.x * b,
+            self.y * a + other.y * b,
+            self.z * a + other.z * b,
+        )
+    }
+    /// Матрица поворота N x N (N >= 3), остальные элементы как у единичной
+    pub fn to_matrix<const N: usize>(self) -> Matrix<N, N, T> {
+        assert!(N >= 3);
+        let q = self.normalize();
+        let (w, x, y, z) = (q.w, q.x, q.y, q.z);
+        let one = T::one();
+        let two = one + one;
+
+        let mut m: Matrix<N, N, T> = Matrix::identity();
+        m[0][0] = one - two * (y * y + z * z);
+        m[0][1] = two * (x * y - w * z);
+        m[0][2] = two * (x * z + w * y);
+        m[1][0] = two * (x * y + w * z);
+        m[1][1] = one - two * (x * x + z * z);
+        m[1][2] = two * (y * z - w * x);
+        m[2][0] = two * (x * z - w * y);
+        m[2][1] = two * (y * z + w * x);
+        m[2][2] = one - two * (x * x + y * y);
+        m
+    }
+    /// Берёт поворот из левого верхнего блока 3 x 3 (без масштаба)
+    pub fn from_matrix<const N: usize>(m: &Matrix<N, N, T>) -> Self {
+        assert!(N >= 3);
+        let one = T::one();
+        let two = one + one;
+        let quarter = one / (two * two);
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        let q = if trace > T::zero() {
+            let s = (trace + one).sqrt() * two;
+            Quaternion::new(quarter * s, (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (one + m[0][0] - m[1][1] - m[2][2]).sqrt() * two;
+            Quaternion::new((m[2][1] - m[1][2]) / s, quarter * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = (one + m[1][1] - m[0][0] - m[2][2]).sqrt() * two;
+            Quaternion::new((m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, quarter * s, (m[1][2] + m[2][1]) / s)
+        } else {
+            let s = (one + m[2][2] - m[0][0] - m[1][1]).sqrt() * two;
+            Quaternion::new((m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, quarter * s)
+        };
+        q.normalize()
+    }
+}
+
+impl<T: Float> Mul for Quaternion<T> {
+    type Output = Quaternion<T>;
+
+    fn mul(self, other: Quaternion<T>) -> Self::Output {
+        Quaternion::new(
+            self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+        )
+    }
+}
+
+impl<T: Float> Mul<Vector<3, T>> for Quaternion<T> {
+    type Output = Vector<3, T>;
+
+    fn mul(self, v: Vector<3, T>) -> Self::Output {
+        self.rotate(v)
+    }
+}
+
+impl<T: Float> Neg for Quaternion<T> {
+    type Output = Quaternion<T>;
+
+    fn neg(self) -> Self::Output {
+        Quaternion::new(-self.w, -self.x, -self.y, -self.z)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vector<3, f32>,
+    pub max: Vector<3, f32>,
+}
+
+impl Aabb {
+    /// Пустой бокс: min = +inf, max = -inf, любой extend делает его непустым
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vector::new([f32::MAX; 3]),
+            max: Vector::new([-f32::MAX; 3]),
+        }
+    }
+    pub fn new(min: Vector<3, f32>, max: Vector<3, f32>) -> Aabb {
+        Aabb { min, max }
+    }
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a Vector<3, f32>>) -> Aabb {
+        let mut aabb = Aabb::empty();
+        for p in points {
+            aabb.extend(*p);
+        }
+        aabb
+    }
+    pub fn is_empty(&self) -> bool {
+        self.min.x() > self.max.x() || self.min.y() > self.max.y() || self.min.z() > self.max.z()
+    }
+    pub fn extend(&mut self, p: Vector<3, f32>) {
+        self.min = self.min.min(p);
+        self.max = self.max.max(p);
+    }
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+    pub fn center(&self) -> Vector<3, f32> {
+        (self.min + self.max) * 0.5
+    }
+    pub fn size(&self) -> Vector<3, f32> {
+        self.max - self.min
+    }
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let d = self.size();
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+    /// Ось (0, 1, 2), вдоль которой бокс длиннее всего
+    pub fn longest_axis(&self) -> usize {
+        let d = self.size();
+        if d.x() >= d.y() && d.x() >= d.z() {
+            0
+        } else if d.y() >= d.z() {
+            1
+        } else {
+            2
+        }
+    }
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|i| self.min[i] <= other.max[i] && other.min[i] <= self.max[i])
+    }
+    pub fn contains(&self, p: Vector<3, f32>) -> bool {
+        (0..3).all(|i| p[i] >= self.min[i] && p[i] <= self.max[i])
+    }
+    pub fn corners(&self) -> [Vector<3, f32>; 8] {
+        let mut corners = [self.min; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            for axis in 0..3 {
+                if i & (1 << axis) != 0 {
+                    corner[axis] = self.max[axis];
+                }
+            }
+        }
+        corners
+    }
+    /// Бокс, охватывающий преобразованный бокс (по восьми углам)
+    pub fn transform(&self, m: &Matrix<4, 4>) -> Aabb {
+        let mut aabb = Aabb::empty();
+        for corner in self.corners() {
+            let p = m * corner.embed::<4>(1.0);
+            aabb.extend(p.xyz() / p.w());
+        }
+        aabb
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sphere {
+    pub center: Vector<3, f32>,
+    pub radius: f32,
+}
+
+impl Sphere {
+    pub fn new(center: Vector<3, f32>, radius: f32) -> Sphere {
+        Sphere { center, radius }
+    }
+    /// Ограничивающая сфера по алгоритму Риттера: не минимальная, но не больше минимальной на ~5-20%
+    pub fn from_points(points: &[Vector<3, f32>]) -> Sphere {
+        if points.is_empty() {
+            return Sphere::new(Vector::empty(), 0.0);
+        }
+        let farthest = |from: Vector<3, f32>| {
+            *points.iter()
+                .max_by(|a, b| (**a - from).norm().total_cmp(&(**b - from).norm()))
+                .unwrap()
+        };
+        let a = farthest(points[0]);
+        let b = farthest(a);
+
+        let mut sphere = Sphere::new((a + b) * 0.5, (b - a).norm() * 0.5);
+        for p in points {
+            let d = (*p - sphere.center).norm();
+            if d > sphere.radius {
+                let radius = (sphere.radius + d) * 0.5;
+                sphere.center = sphere.center + (*p - sphere.center) * ((radius - sphere.radius) / d);
+                sphere.radius = radius;
+            }
+        }
+        sphere
+    }
+    /// Расстояние от центра, с которого сфера целиком видна камере с вертикальным углом обзора fov_y
+    pub fn framing_distance(&self, fov_y: f32) -> f32 {
+        self.radius / (fov_y / 2.0).sin()
+    }
+    /// Видовая матрица камеры, смотрящей на центр сферы в направлении view_dir так, чтобы сфера заполняла кадр
+    pub fn frame(&self, view_dir: Vector<3, f32>, up: Vector<3, f32>, fov_y: f32) -> Matrix<4, 4> {
+        let eye = self.center - view_dir.normalize(self.framing_distance(fov_y));
+        Matrix::look_at(eye, self.center, up)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vector<3, f32>,
+    pub dir: Vector<3, f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Vector<3, f32>, dir: Vector<3, f32>) -> Ray {
+        Ray { origin, dir }
+    }
+    /// Луч из камеры через точку экрана в NDC (-1..1); inv_view_proj - обратная к projection * model_view
+    pub fn unproject(ndc_x: f32, ndc_y: f32, inv_view_proj: &Matrix<4, 4>) -> Ray {
+        let near = inv_view_proj * Vector::new([ndc_x, ndc_y, -1.0, 1.0]);
+        let far = inv_view_proj * Vector::new([ndc_x, ndc_y, 1.0, 1.0]);
+        let near = near.xyz() / near.w();
+        let far = far.xyz() / far.w();
+        Ray::new(near, (far - near).normalize(1.0))
+    }
+    pub fn at(&self, t: f32) -> Vector<3, f32> {
+        self.origin + self.dir * t
+    }
+    /// Пересечение с треугольником (Möller–Trumbore), обе стороны.
+    /// Возвращает расстояние t и барицентрические координаты точки относительно (a, b, c)
+    pub fn intersect_triangle(&self, a: Vector<3, f32>, b: Vector<3, f32>, c: Vector<3, f32>) -> Option<(f32, Vector<3, f32>)> {
+        let e1 = b - a;
+        let e2 = c - a;
+        let p = self.dir ^ e2;
+        let det = e1 * p;
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let s = self.origin - a;
+        let u = (s * p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s ^ e1;
+        let v = (self.dir * q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = (e2 * q) * inv_det;
+        if t < 0.0 {
+            return None;
+        }
+        Some((t, Vector::new([1.0 - u - v, u, v])))
+    }
+    /// Пересечение с боксом методом плит: отрезок [t_near, t_far] внутри бокса, t_far >= 0
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<(f32, f32)> {
+        let mut t_near = 0.0_f32;
+        let mut t_far = f32::MAX;
+        for i in 0..3 {
+            let inv = 1.0 / self.dir[i];
+            let mut t0 = (aabb.min[i] - self.origin[i]) * inv;
+            let mut t1 = (aabb.max[i] - self.origin[i]) * inv;
+            if inv < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN (луч в плоскости грани при dir[i] == 0) не сужает интервал
+            t_near = if t0 > t_near { t0 } else { t_near };
+            t_far = if t1 < t_far { t1 } else { t_far };
+            if t_far < t_near {
+                return None;
+            }
+        }
+        Some((t_near, t_far))
+    }
+    /// Ближайшее пересечение со сферой при t >= 0 (если начало внутри - выход из сферы)
+    pub fn intersect_sphere(&self, sphere: &Sphere) -> Option<f32> {
+        let oc = self.origin - sphere.center;
+        let a = self.dir * self.dir;
+        let half_b = oc * self.dir;
+        let c = oc * oc - sphere.radius * sphere.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrt = discriminant.sqrt();
+        let t0 = (-half_b - sqrt) / a;
+        let t1 = (-half_b + sqrt) / a;
+        if t0 >= 0.0 {
+            Some(t0)
+        } else if t1 >= 0.0 {
+            Some(t1)
+        } else {
+            None
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Плоскость normal * p + d = 0, положительное полупространство - "внутри"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: Vector<3, f32>,
+    pub d: f32,
+}
+
+impl Plane {
+    pub fn new(normal: Vector<3, f32>, d: f32) -> Plane {
+        Plane { normal, d }
+    }
+    pub fn distance(&self, p: Vector<3, f32>) -> f32 {
+        self.normal * p + self.d
+    }
+    fn normalized(&self) -> Plane {
+        let len = self.normal.norm();
+        Plane::new(self.normal / len, self.d / len)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// left, right, bottom, top, near, far
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Плоскости отсечения из projection * model_view (метод Грибба-Хартманна):
+    /// точка внутри, если -w <= x, y, z <= w в пространстве отсечения
+    pub fn from_matrix(m: &Matrix<4, 4>) -> Frustum {
+        let plane = |row: usize, sign: f32| {
+            let v = m[3] + m[row] * sign;
+            Plane::new(v.xyz(), v.w()).normalized()
+        };
+        Frustum {
+            planes: [
+                plane(0, 1.0),
+                plane(0, -1.0),
+                plane(1, 1.0),
+                plane(1, -1.0),
+                plane(2, 1.0),
+                plane(2, -1.0),
+            ],
+        }
+    }
+    pub fn contains_point(&self, p: Vector<3, f32>) -> bool {
+        self.planes.iter().all(|plane| plane.distance(p) >= 0.0)
+    }
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes.iter().all(|plane| plane.distance(sphere.center) >= -sphere.radius)
+    }
+    /// Консервативный тест: false только если бокс целиком снаружи одной из плоскостей
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // вершина бокса, дальше всего продвинутая вдоль нормали
+            let mut p = aabb.min;
+            for i in 0..3 {
+                if plane.normal[i] >= 0.0 {
+                    p[i] = aabb.max[i];
+                }
+            }
+            plane.distance(p) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    use proptest::{array::{uniform3, uniform4}, prelude::*};
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    const EPS: f32 = 1e-4;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < EPS, "{a} != {b}");
+    }
+    fn assert_vector_close<const N: usize>(a: Vector<N, f32>, b: Vector<N, f32>) {
+        for i in 0..N {
+            assert!((a[i] - b[i]).abs() < EPS, "{a:?} != {b:?}");
+        }
+    }
+    fn assert_matrix_close<const R: usize, const C: usize>(a: &Matrix<R, C>, b: &Matrix<R, C>) {
+        for i in 0..R {
+            for j in 0..C {
+                assert!((a[i][j] - b[i][j]).abs() < EPS, "{a:?} != {b:?}");
+            }
+        }
+    }
+    // q и -q - один и тот же поворот
+    fn assert_rotation_close(a: Quaternion<f32>, b: Quaternion<f32>) {
+        assert!((a.dot(&b).abs() - 1.0).abs() < EPS, "{a:?} != {b:?}");
+    }
+    fn random_axis(rng: &mut StdRng) -> Vector<3, f32> {
+        loop {
+            let v = Vector::new([(); 3].map(|_| rng.random_range(-1.0..1.0)));
+            if v.norm() > 0.1 {
+                return v.normalize(1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn quaternion_euler_round_trip() {
+        let mut rng = StdRng::seed_from_u64(28);
+        for _ in 0..1000 {
+            let x = rng.random_range(-PI..PI);
+            let y = rng.random_range(-FRAC_PI_2 + 0.01..FRAC_PI_2 - 0.01);
+            let z = rng.random_range(-PI..PI);
+            let (ex, ey, ez) = Quaternion::from_euler(x, y, z).to_euler();
+            assert_rotation_close(Quaternion::from_euler(ex, ey, ez), Quaternion::from_euler(x, y, z));
+            assert_close(ey, y);
+        }
+    }
+
+    #[test]
+    fn quaternion_axis_angle_round_trip() {
+        let mut rng = StdRng::seed_from_u64(29);
+        for _ in 0..1000 {
+            let axis = random_axis(&mut rng);
+            let angle = rng.random_range(0.1..PI);
+            let (a, b) = Quaternion::from_axis_angle(axis, angle).to_axis_angle();
+            assert_vector_close(a, axis);
+            assert_close(b, angle);
+        }
+        let (axis, angle) = Quaternion::<f32>::identity().to_axis_angle();
+        assert_vector_close(axis, Vector::new([1.0, 0.0, 0.0]));
+        assert_close(angle, 0.0);
+    }
+
+    #[test]
+    fn quaternion_matrix_round_trip() {
+        let mut rng = StdRng::seed_from_u64(30);
+        for _ in 0..1000 {
+            let q = Quaternion::from_axis_angle(random_axis(&mut rng), rng.random_range(-PI..PI));
+            let m: Matrix<4, 4> = q.to_matrix();
+            assert_rotation_close(Quaternion::from_matrix(&m), q);
+            let v = random_axis(&mut rng);
+            assert_vector_close((&m * v.embed::<4>(0.0)).xyz(), q.rotate(v));
+        }
+        // поворот на 90 градусов вокруг z переводит x в y
+        let m: Matrix<3, 3> = Quaternion::from_axis_angle(Vector::new([0.0, 0.0, 1.0]), FRAC_PI_2).to_matrix();
+        assert_matrix_close(&m, &Matrix::from_rows([
+            Vector::new([0.0, -1.0, 0.0]),
+            Vector::new([1.0, 0.0, 0.0]),
+            Vector::new([0.0, 0.0, 1.0]),
+        ]));
+    }
+
+    #[test]
+    fn quaternion_slerp_endpoints() {
+        let mut rng = StdRng::seed_from_u64(31);
+        for _ in 0..1000 {
+            let a = Quaternion::from_axis_angle(random_axis(&mut rng), rng.random_range(-PI..PI));
+            let b = Quaternion::from_axis_angle(random_axis(&mut rng), rng.random_range(-PI..PI));
+            assert_rotation_close(a.slerp(&b, 0.0), a);
+            assert_rotation_close(a.slerp(&b, 1.0), b);
+            assert_close(a.slerp(&b, 0.5).norm(), 1.0);
+        }
+    }
+
+    #[test]
+    fn quaternion_slerp_takes_short_arc() {
+        let z = Vector::new([0.0, 0.0, 1.0]);
+        let a = Quaternion::identity();
+        // -b - тот же поворот на 0.5, без разворота slerp пошёл бы по длинной дуге
+        let b = -Quaternion::from_axis_angle(z, 0.5);
+        assert!(a.dot(&b) < 0.0);
+        assert_rotation_close(a.slerp(&b, 0.5), Quaternion::from_axis_angle(z, 0.25));
+        assert_rotation_close(a.nlerp(&b, 0.5), Quaternion::from_axis_angle(z, 0.25));
+    }
+
+    fn matrix(rows: [[f32; 4]; 4]) -> Matrix<4, 4> {
+        Matrix::from_rows(rows.map(Vector::new))
+    }
+    fn point(m: &Matrix<4, 4>, p: [f32; 3]) -> Vector<3, f32> {
+        let p = m * Vector::new(p).embed::<4>(1.0);
+        p.xyz() / p.w()
+    }
+
+    #[test]
+    fn translation_and_scaling() {
+        assert_matrix_close(&Matrix::translation(Vector::new([1.0, 2.0, 3.0])), &matrix([
+            [1.0, 0.0, 0.0, 1.0],
+            [0.0, 1.0, 0.0, 2.0],
+            [0.0, 0.0, 1.0, 3.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]));
+        assert_matrix_close(&Matrix::scaling(Vector::new([2.0, 3.0, 4.0])), &matrix([
+            [2.0, 0.0, 0.0, 0.0],
+            [0.0, 3.0, 0.0, 0.0],
+            [0.0, 0.0, 4.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]));
+    }
+
+    #[test]
+    fn rotations() {
+        // против часовой стрелки, если смотреть с конца оси: x -> y, y -> z, z -> x
+        assert_matrix_close(&Matrix::rotation_x(FRAC_PI_2), &matrix([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, -1.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]));
+        assert_matrix_close(&Matrix::rotation_y(FRAC_PI_2), &matrix([
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]));
+        assert_matrix_close(&Matrix::rotation_z(FRAC_PI_2), &matrix([
+            [0.0, -1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]));
+        for angle in [-2.0, 0.3, 1.0, 3.0] {
+            assert_matrix_close(&Matrix::rotation(Vector::new([1.0, 0.0, 0.0]), angle), &Matrix::rotation_x(angle));
+            assert_matrix_close(&Matrix::rotation(Vector::new([0.0, 2.0, 0.0]), angle), &Matrix::rotation_y(angle));
+            assert_matrix_close(&Matrix::rotation(Vector::new([0.0, 0.0, 1.0]), angle), &Matrix::rotation_z(angle));
+        }
+    }
+
+    #[test]
+    fn perspective() {
+        // fov 90 градусов: f = 1
+        let m = Matrix::perspective(FRAC_PI_2, 2.0, 1.0, 3.0);
+        assert_matrix_close(&m, &matrix([
+            [0.5, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, -2.0, -3.0],
+            [0.0, 0.0, -1.0, 0.0],
+        ]));
+        assert_close(point(&m, [0.0, 0.0, -1.0]).z(), -1.0);
+        assert_close(point(&m, [0.0, 0.0, -3.0]).z(), 1.0);
+        // угол пирамиды видимости на near
+        assert_vector_close(point(&m, [2.0, 1.0, -1.0]), Vector::new([1.0, 1.0, -1.0]));
+    }
+
+    #[test]
+    fn orthographic() {
+        let m = Matrix::orthographic(-2.0, 2.0, -1.0, 3.0, 1.0, 5.0);
+        assert_matrix_close(&m, &matrix([
+            [0.5, 0.0, 0.0, 0.0],
+            [0.0, 0.5, 0.0, -0.5],
+            [0.0, 0.0, -0.5, -1.5],
+            [0.0, 0.0, 0.0, 1.0],
+        ]));
+        assert_vector_close(point(&m, [-2.0, -1.0, -1.0]), Vector::new([-1.0, -1.0, -1.0]));
+        assert_vector_close(point(&m, [2.0, 3.0, -5.0]), Vector::new([1.0, 1.0, 1.0]));
+    }
+
+    #[test]
+    fn look_at() {
+        // камера на +z смотрит в начало координат: вид - перенос на -5 по z
+        let m = Matrix::look_at(Vector::new([0.0, 0.0, 5.0]), Vector::new([0.0, 0.0, 0.0]), Vector::new([0.0, 1.0, 0.0]));
+        assert_matrix_close(&m, &Matrix::translation(Vector::new([0.0, 0.0, -5.0])));
+        // камера на +x: мировой -z оказывается справа, x - позади
+        let m = Matrix::look_at(Vector::new([3.0, 0.0, 0.0]), Vector::new([0.0, 0.0, 0.0]), Vector::new([0.0, 1.0, 0.0]));
+        assert_matrix_close(&m, &matrix([
+            [0.0, 0.0, -1.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0, -3.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]));
+        assert_vector_close(point(&m, [0.0, 0.0, 0.0]), Vector::new([0.0, 0.0, -3.0]));
+    }
+
+    #[test]
+    fn viewport() {
+        let m = Matrix::viewport(10.0, 20.0, 100.0, 50.0, 255.0);
+        assert_matrix_close(&m, &matrix([
+            [50.0, 0.0, 0.0, 60.0],
+            [0.0, 25.0, 0.0, 45.0],
+            [0.0, 0.0, 127.5, 127.5],
+            [0.0, 0.0, 0.0, 1.0],
+        ]));
+        assert_vector_close(point(&m, [-1.0, -1.0, -1.0]), Vector::new([10.0, 20.0, 0.0]));
+        assert_vector_close(point(&m, [1.0, 1.0, 1.0]), Vector::new([110.0, 70.0, 255.0]));
+    }
+
+    fn random_matrix<const N: usize>(rng: &mut StdRng) -> Matrix<N, N> {
+        let mut m = Matrix::new();
+        for i in 0..N {
+            for j in 0..N {
+                m[i][j] = rng.random_range(-1.0..1.0);
+            }
+            // диагональное преобладание - обратима и хорошо обусловлена
+            m[i][i] += N as f32;
+        }
         m
     }
-}
\ No newline at end of file
+    fn assert_inverse<const N: usize>(rng: &mut StdRng) {
+        for _ in 0..100 {
+            let m = random_matrix::<N>(rng);
+            let inv = m.inverse().unwrap();
+            assert_matrix_close(&(&inv * &m), &Matrix::identity());
+            assert_matrix_close(&(&m * &inv), &Matrix::identity());
+        }
+    }
+
+    #[test]
+    fn inverse_closed_form_and_lu() {
+        let mut rng = StdRng::seed_from_u64(300);
+        assert_inverse::<1>(&mut rng);
+        assert_inverse::<2>(&mut rng);
+        assert_inverse::<3>(&mut rng);
+        assert_inverse::<4>(&mut rng);
+        // через LU
+        assert_inverse::<5>(&mut rng);
+        assert_inverse::<7>(&mut rng);
+    }
+
+    #[test]
+    fn integer_matrices() {
+        let a: Matrix<2, 3, i32> = Matrix::from_rows([Vector::new([1, 2, 3]), Vector::new([4, 5, 6])]);
+        let b: Matrix<3, 2, i32> = Matrix::from_rows([Vector::new([7, 8]), Vector::new([9, 10]), Vector::new([11, 12])]);
+        let ab = &a * &b;
+        assert_eq!(ab, Matrix::from_rows([Vector::new([58, 64]), Vector::new([139, 154])]));
+        assert_eq!(a.transpose().transpose(), a);
+        assert_eq!(a.transpose(), Matrix::from_rows([Vector::new([1, 4]), Vector::new([2, 5]), Vector::new([3, 6])]));
+        // (AB)^T = B^T A^T
+        assert_eq!(ab.transpose(), &b.transpose() * &a.transpose());
+        assert_eq!(&Matrix::<2, 2, i32>::identity() * &a, a);
+        assert_eq!(&a * &Matrix::<3, 3, i32>::identity(), a);
+        assert_eq!(&a * Vector::new([1, 0, -1]), Vector::new([-2, -2]));
+        // целочисленная матрица с определителем 1 обращается точно, через f64
+        let m: Matrix<3, 3, i64> = Matrix::from_rows([Vector::new([2, 1, 0]), Vector::new([1, 1, 0]), Vector::new([0, 3, 1])]);
+        let inverse_f64 = m.cast::<f64>().inverse().unwrap();
+        let mut inverse: Matrix<3, 3, i64> = Matrix::new();
+        for i in 0..3 {
+            inverse[i] = inverse_f64[i].round();
+        }
+        assert_eq!(&m * &inverse, Matrix::identity());
+        assert_eq!(inverse, Matrix::from_rows([Vector::new([1, -1, 0]), Vector::new([-1, 2, 0]), Vector::new([3, -6, 1])]));
+    }
+
+    #[test]
+    fn f64_matrices() {
+        let mut rng = StdRng::seed_from_u64(31);
+        let identity: Matrix<4, 4, f64> = Matrix::identity();
+        for _ in 0..100 {
+            let mut a: Matrix<4, 4, f64> = Matrix::new();
+            let mut b: Matrix<4, 4, f64> = Matrix::new();
+            for i in 0..4 {
+                a[i] = Vector::new([(); 4].map(|_| rng.random_range(-10.0..10.0)));
+                b[i] = Vector::new([(); 4].map(|_| rng.random_range(-10.0..10.0)));
+            }
+            assert_eq!(&a * &identity, a);
+            assert_eq!(&identity * &a, a);
+            assert_eq!(a.transpose().transpose(), a);
+            let ab = &a * &b;
+            let bt_at = &b.transpose() * &a.transpose();
+            for i in 0..4 {
+                for j in 0..4 {
+                    assert!((ab.transpose()[i][j] - bt_at[i][j]).abs() < 1e-12);
+                }
+            }
+            // f64 обращает точнее f32: допуск на порядки меньше EPS
+            let Some(inverse) = a.inverse() else { continue };
+            let product = &a * &inverse;
+            let scale = (0..4).map(|i| a[i].norm()).product::<f64>() / a.determinant().abs();
+            for i in 0..4 {
+                for j in 0..4 {
+                    assert!((product[i][j] - identity[i][j]).abs() < 1e-12 * scale.max(1.0), "{product:?}");
+                }
+            }
+            let lu_inverse = a.lu().unwrap().inverse();
+            for i in 0..4 {
+                for j in 0..4 {
+                    assert!((lu_inverse[i][j] - inverse[i][j]).abs() < 1e-9 * scale.max(1.0));
+                }
+            }
+        }
+        // 6x6 через LU
+        let mut m: Matrix<6, 6, f64> = Matrix::identity();
+        m[0][5] = 3.0;
+        m[4][1] = -2.0;
+        m[2][2] = 0.5;
+        let product = &m * &m.inverse().unwrap();
+        for i in 0..6 {
+            for j in 0..6 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((product[i][j] - expected).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn small_uniform_scales() {
+        // модель в миллиметрах: определитель крошечный, но матрица хорошо обусловлена
+        for scale in [3e-4, 1e-4, 1e-6] {
+            let m4 = Matrix::<4, 4>::scaling(Vector::new([scale; 3]));
+            assert_matrix_close(&(&m4.inverse().unwrap() * &m4), &Matrix::identity());
+            let mut m2: Matrix<2, 2> = Matrix::identity();
+            let mut m3: Matrix<3, 3> = Matrix::identity();
+            let mut m6: Matrix<6, 6> = Matrix::identity();
+            for i in 0..6 {
+                if i < 2 {
+                    m2[i][i] = scale;
+                }
+                if i < 3 {
+                    m3[i][i] = scale;
+                }
+                m6[i][i] = scale;
+            }
+            assert_matrix_close(&(&m2.inverse().unwrap() * &m2), &Matrix::identity());
+            assert_matrix_close(&(&m3.inverse().unwrap() * &m3), &Matrix::identity());
+            assert_matrix_close(&(&m6.inverse().unwrap() * &m6), &Matrix::identity());
+            // перенос удлиняет строки, явная формула сдаётся и считает LU
+            let moved = &Matrix::translation(Vector::new([10.0, -5.0, 2.0])) * &m4;
+            assert_matrix_close(&(&moved.inverse().unwrap() * &moved), &Matrix::identity());
+        }
+    }
+
+    #[test]
+    fn singular_matrices() {
+        // вторая строка кратна первой
+        let m2 = Matrix::from_rows([Vector::new([1.0, 2.0]), Vector::new([2.0, 4.0])]);
+        assert!(m2.inverse().is_none());
+        assert_close(m2.determinant(), 0.0);
+        let m3 = Matrix::from_rows([Vector::new([1.0, 2.0, 3.0]), Vector::new([4.0, 5.0, 6.0]), Vector::new([7.0, 8.0, 9.0])]);
+        assert!(m3.inverse().is_none());
+        assert_close(m3.determinant(), 0.0);
+        let mut m4 = matrix([[1.0, 2.0, 3.0, 4.0], [0.0, 1.0, 0.0, 0.0], [2.0, 4.0, 6.0, 8.0], [0.0, 0.0, 0.0, 1.0]]);
+        assert!(m4.inverse().is_none());
+        m4[2][2] = 7.0;
+        assert!(m4.inverse().is_some());
+        // нулевой столбец у матрицы 5x5
+        let mut m5 = random_matrix::<5>(&mut StdRng::seed_from_u64(301));
+        for i in 0..5 {
+            m5[i][3] = 0.0;
+        }
+        assert!(m5.lu().is_none());
+        assert!(m5.inverse().is_none());
+        assert!(m5.solve(Vector::new([1.0; 5])).is_none());
+        assert_close(m5.determinant(), 0.0);
+    }
+
+    #[test]
+    fn determinant_with_pivoting() {
+        // перестановка строк меняет знак: det = -1 * 2 * 3 * 4 * 5
+        let mut m = Matrix::<5, 5>::new();
+        for (i, v) in [2.0, 1.0, 3.0, 4.0, 5.0].into_iter().enumerate() {
+            m[i][i] = v;
+        }
+        m.matrix.swap(0, 1);
+        assert_close(m.determinant(), -120.0);
+        assert_close(m.lu().unwrap().determinant(), -120.0);
+        let mut rng = StdRng::seed_from_u64(302);
+        for _ in 0..100 {
+            // явная формула 3x3 и LU считают одно и то же
+            let m = random_matrix::<3>(&mut rng);
+            assert!((m.determinant() - m.lu().unwrap().determinant()).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn solve() {
+        let a = Matrix::from_rows([Vector::new([2.0, 1.0, -1.0]), Vector::new([-3.0, -1.0, 2.0]), Vector::new([-2.0, 1.0, 2.0])]);
+        assert_vector_close(a.solve(Vector::new([8.0, -11.0, -3.0])).unwrap(), Vector::new([2.0, 3.0, -1.0]));
+        let mut rng = StdRng::seed_from_u64(303);
+        for _ in 0..100 {
+            let a = random_matrix::<6>(&mut rng);
+            let x = Vector::new([(); 6].map(|_| rng.random_range(-10.0..10.0)));
+            let solved = a.solve(&a * x).unwrap();
+            for i in 0..6 {
+                assert!((solved[i] - x[i]).abs() < 1e-3, "{solved:?} != {x:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_rigid() {
+        let mut rng = StdRng::seed_from_u64(304);
+        for _ in 0..100 {
+            let t = Vector::new([(); 3].map(|_| rng.random_range(-10.0..10.0)));
+            let m = Matrix::translation(t) * Matrix::rotation(random_axis(&mut rng), rng.random_range(-PI..PI));
+            let inv = m.inverse_rigid();
+            assert_matrix_close(&(&inv * &m), &Matrix::identity());
+            let general = m.inverse().unwrap();
+            for i in 0..4 {
+                for j in 0..4 {
+                    assert!((inv[i][j] - general[i][j]).abs() < 1e-3);
+                }
+            }
+        }
+    }
+
+    fn vector3() -> impl Strategy<Value = Vector<3, f32>> {
+        uniform3(-10.0f32..10.0).prop_map(Vector::new)
+    }
+    fn vector4() -> impl Strategy<Value = Vector<4, f32>> {
+        uniform4(-10.0f32..10.0).prop_map(Vector::new)
+    }
+    fn matrix4() -> impl Strategy<Value = Matrix<4, 4>> {
+        uniform4(uniform4(-10.0f32..10.0)).prop_map(matrix)
+    }
+    fn max_abs<const R: usize, const C: usize>(m: &Matrix<R, C>) -> f32 {
+        (0..R).flat_map(|i| (0..C).map(move |j| m[i][j].abs())).fold(0.0, f32::max)
+    }
+
+    proptest! {
+        #[test]
+        fn prop_inverse_times_matrix_is_identity(m in matrix4()) {
+            prop_assume!(m.determinant().abs() > 1.0);
+            let inv = m.inverse().unwrap();
+            // погрешность растёт с обусловленностью, её оценка - произведение норм
+            let tolerance = 1e-5 * (1.0 + max_abs(&m) * max_abs(&inv)) * 16.0;
+            let product = &inv * &m;
+            for i in 0..4 {
+                for j in 0..4 {
+                    let expected = if i == j { 1.0 } else { 0.0 };
+                    prop_assert!((product[i][j] - expected).abs() < tolerance, "{product:?}");
+                }
+            }
+        }
+
+        #[test]
+        fn prop_transpose_is_involution(m in matrix4()) {
+            prop_assert_eq!(m.transpose().transpose(), m.clone());
+            for i in 0..4 {
+                prop_assert_eq!(m.transpose()[i], m.col(i));
+            }
+        }
+
+        #[test]
+        fn prop_cross_is_orthogonal(a in vector3(), b in vector3()) {
+            let c = a ^ b;
+            let tolerance = 1e-4 * (1.0 + a.norm() * a.norm() * b.norm());
+            prop_assert!((c * a).abs() < tolerance);
+            prop_assert!((c * b).abs() < tolerance);
+            prop_assert_eq!(b ^ a, -c);
+        }
+
+        #[test]
+        fn prop_dot_is_symmetric(a in vector4(), b in vector4()) {
+            prop_assert_eq!(a * b, b * a);
+        }
+
+        #[test]
+        fn prop_embed_proj_round_trip(v in vector3(), fill in -10.0f32..10.0) {
+            let embedded = v.embed::<4>(fill);
+            prop_assert_eq!(embedded.proj::<3>(), v);
+            prop_assert_eq!(embedded[3], fill);
+            prop_assert_eq!(v.embed::<5>(fill).proj::<3>(), v);
+        }
+
+        #[test]
+        fn prop_set_col_then_col(m in matrix4(), v in vector4(), idx in 0usize..4) {
+            let mut m = m;
+            m.set_col(idx, v);
+            prop_assert_eq!(m.col(idx), v);
+        }
+    }
+
+    // set_col перебирал пустой диапазон ROWS..0 и ничего не записывал
+    #[test]
+    fn set_col_regression() {
+        let mut m = Matrix::<3, 2>::new();
+        m.set_col(1, Vector::new([1.0, 2.0, 3.0]));
+        assert_eq!(m.col(1), Vector::new([1.0, 2.0, 3.0]));
+        assert_eq!(m.col(0), Vector::new([0.0, 0.0, 0.0]));
+    }
+
+    // ^ считал other[a] * self[b] - self[b] * other[a], то есть всегда ноль
+    #[test]
+    fn cross_regression() {
+        let x = Vector::new([1.0, 0.0, 0.0]);
+        let y = Vector::new([0.0, 1.0, 0.0]);
+        let z = Vector::new([0.0, 0.0, 1.0]);
+        assert_eq!(x ^ y, z);
+        assert_eq!(y ^ z, x);
+        assert_eq!(z ^ x, y);
+        assert_eq!(Vector::new([1.0, 2.0, 3.0]) ^ Vector::new([4.0, 5.0, 6.0]), Vector::new([-3.0, 6.0, -3.0]));
+    }
+
+    #[test]
+    fn vector_min_max_clamp_abs() {
+        let a = Vector::new([1.0, -2.0, 3.0]);
+        let b = Vector::new([-1.0, 5.0, 3.0]);
+        assert_eq!(a.min(b), Vector::new([-1.0, -2.0, 3.0]));
+        assert_eq!(a.max(b), Vector::new([1.0, 5.0, 3.0]));
+        assert_eq!(Vector::new([-5.0, 0.5, 5.0]).clamp(Vector::new([-1.0; 3]), Vector::new([1.0; 3])), Vector::new([-1.0, 0.5, 1.0]));
+        assert_eq!(a.abs(), Vector::new([1.0, 2.0, 3.0]));
+        assert_eq!(Vector::new([-3, 0, 4]).abs(), Vector::new([3, 0, 4]));
+        assert_eq!(Vector::new([7, -1]).clamp(Vector::new([0, 0]), Vector::new([5, 5])), Vector::new([5, 0]));
+    }
+
+    #[test]
+    fn vector_component_ops() {
+        let a = Vector::new([1.0, -2.0, 3.0]);
+        let b = Vector::new([4.0, 0.5, -1.0]);
+        assert_eq!(a.component_mul(b), Vector::new([4.0, -1.0, -3.0]));
+        assert_eq!(a.component_mul(b).component_div(b), a);
+        assert_eq!(Vector::new([6, 7]).component_div(Vector::new([2, 2])), Vector::new([3, 3]));
+    }
+
+    #[test]
+    fn vector_lerp() {
+        let a = Vector::new([0.0, 2.0, -4.0]);
+        let b = Vector::new([2.0, 4.0, 4.0]);
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert_eq!(a.lerp(b, 0.5), Vector::new([1.0, 3.0, 0.0]));
+        assert_eq!(a.lerp(b, 1.0), b);
+    }
+
+    #[test]
+    fn vector_reflect() {
+        let up = Vector::new([0.0, 1.0, 0.0]);
+        assert_eq!(Vector::new([1.0, -1.0, 0.0]).reflect(up), Vector::new([1.0, 1.0, 0.0]));
+        // касательный не меняется, перпендикулярный разворачивается
+        assert_eq!(Vector::new([0.0, 0.0, 1.0]).reflect(up), Vector::new([0.0, 0.0, 1.0]));
+        assert_eq!(Vector::new([0.0, -1.0, 0.0]).reflect(up), up);
+        assert_eq!(Vector::new([2.0, 3.0]).reflect(Vector::new([1.0, 0.0])), Vector::new([-2.0, 3.0]));
+    }
+
+    #[test]
+    fn vector_refract() {
+        let up = Vector::new([0.0, 1.0, 0.0]);
+        let down = Vector::new([0.0, -1.0, 0.0]);
+        // по нормали и при eta = 1 направление не меняется
+        assert_vector_close(down.refract(up, 1.5).unwrap(), down);
+        let incident = Vector::new([1.0, -1.0, 0.0]).normalize(1.0);
+        assert_vector_close(incident.refract(up, 1.0).unwrap(), incident);
+        // закон Снеллиуса: sin t = eta * sin i
+        let refracted = incident.refract(up, 1.0 / 1.5).unwrap();
+        assert_close(refracted.norm(), 1.0);
+        assert_close(refracted.x(), 45.0_f32.to_radians().sin() / 1.5);
+        assert!(refracted.y() < 0.0);
+        // из стекла в воздух под 45 градусов - полное внутреннее отражение
+        assert!(incident.refract(up, 1.5).is_none());
+        // чуть меньше критического угла ещё проходит
+        let critical = (1.0_f32 / 1.5).asin() - 0.01;
+        assert!(Vector::new([critical.sin(), -critical.cos(), 0.0]).refract(up, 1.5).is_some());
+    }
+
+    #[test]
+    fn vector_swizzles_and_resize() {
+        let v = Vector::new([1, 2, 3]);
+        assert_eq!((v.x(), v.y(), v.z()), (1, 2, 3));
+        assert_eq!(v.xy(), Vector::new([1, 2]));
+        assert_eq!(v.zx(), Vector::new([3, 1]));
+        assert_eq!(v.zyx(), Vector::new([3, 2, 1]));
+        assert_eq!(v.yzx(), Vector::new([2, 3, 1]));
+        assert_eq!(Vector::new([1, 2]).yx(), Vector::new([2, 1]));
+        let w = Vector::new([1, 2, 3, 4]);
+        assert_eq!(w.w(), 4);
+        assert_eq!(w.xyz(), v);
+        assert_eq!(w.zxy(), Vector::new([3, 1, 2]));
+        // 3 -> 4 дописывает ноль, 4 -> 3 его отбрасывает
+        let wide: Vector<4, i32> = v.into();
+        assert_eq!(wide, Vector::new([1, 2, 3, 0]));
+        assert_eq!(Vector::<3, i32>::from(wide), v);
+        let narrow: Vector<2, i32> = v.into();
+        assert_eq!(Vector::<3, i32>::from(narrow), Vector::new([1, 2, 0]));
+    }
+
+    #[test]
+    fn ray_triangle() {
+        let (a, b, c) = (Vector::new([0.0, 0.0, 0.0]), Vector::new([1.0, 0.0, 0.0]), Vector::new([0.0, 1.0, 0.0]));
+        let down = Vector::new([0.0, 0.0, -1.0]);
+        let (t, bar) = Ray::new(Vector::new([0.25, 0.25, 2.0]), down).intersect_triangle(a, b, c).unwrap();
+        assert_close(t, 2.0);
+        assert_vector_close(bar, Vector::new([0.5, 0.25, 0.25]));
+        // с обратной стороны тоже
+        let (t, _) = Ray::new(Vector::new([0.25, 0.25, -2.0]), -down).intersect_triangle(a, b, c).unwrap();
+        assert_close(t, 2.0);
+        // мимо, позади начала луча, параллельно плоскости
+        assert!(Ray::new(Vector::new([1.0, 1.0, 2.0]), down).intersect_triangle(a, b, c).is_none());
+        assert!(Ray::new(Vector::new([0.25, 0.25, 2.0]), -down).intersect_triangle(a, b, c).is_none());
+        assert!(Ray::new(Vector::new([0.25, 0.25, 0.0]), Vector::new([1.0, 0.0, 0.0])).intersect_triangle(a, b, c).is_none());
+        // рёбра и вершины считаются попаданием
+        for (p, expected) in [([0.5, 0.0], [0.5, 0.5, 0.0]), ([0.5, 0.5], [0.0, 0.5, 0.5]), ([0.0, 0.0], [1.0, 0.0, 0.0]), ([0.0, 1.0], [0.0, 0.0, 1.0])] {
+            let (_, bar) = Ray::new(Vector::new([p[0], p[1], 1.0]), down).intersect_triangle(a, b, c).unwrap();
+            assert_vector_close(bar, Vector::new(expected));
+        }
+        assert!(Ray::new(Vector::new([0.51, 0.51, 1.0]), down).intersect_triangle(a, b, c).is_none());
+    }
+
+    #[test]
+    fn ray_aabb() {
+        let aabb = Aabb::new(Vector::new([-1.0, -1.0, -1.0]), Vector::new([1.0, 1.0, 1.0]));
+        let (near, far) = Ray::new(Vector::new([-3.0, 0.0, 0.0]), Vector::new([1.0, 0.0, 0.0])).intersect_aabb(&aabb).unwrap();
+        assert_close(near, 2.0);
+        assert_close(far, 4.0);
+        // лучи вдоль осей: деление на ноль в остальных компонентах
+        for axis in 0..3 {
+            let mut origin = Vector::new([0.5, -0.5, 0.25]);
+            origin[axis] = 5.0;
+            let mut dir = Vector::empty();
+            dir[axis] = -1.0;
+            let (near, far) = Ray::new(origin, dir).intersect_aabb(&aabb).unwrap();
+            assert_close(near, 4.0);
+            assert_close(far, 6.0);
+            // параллельно оси, но вне бокса
+            let mut outside = origin;
+            outside[(axis + 1) % 3] = 2.0;
+            assert!(Ray::new(outside, dir).intersect_aabb(&aabb).is_none());
+        }
+        // изнутри: t_near = 0
+        let (near, far) = Ray::new(Vector::empty(), Vector::new([0.0, 1.0, 0.0])).intersect_aabb(&aabb).unwrap();
+        assert_close(near, 0.0);
+        assert_close(far, 1.0);
+        // бокс позади
+        assert!(Ray::new(Vector::new([-3.0, 0.0, 0.0]), Vector::new([-1.0, 0.0, 0.0])).intersect_aabb(&aabb).is_none());
+    }
+
+    #[test]
+    fn ray_sphere() {
+        let sphere = Sphere { center: Vector::new([0.0, 0.0, -5.0]), radius: 1.0 };
+        let forward = Vector::new([0.0, 0.0, -1.0]);
+        assert_close(Ray::new(Vector::empty(), forward).intersect_sphere(&sphere).unwrap(), 4.0);
+        // изнутри - точка выхода
+        assert_close(Ray::new(sphere.center, forward).intersect_sphere(&sphere).unwrap(), 1.0);
+        assert_close(Ray::new(sphere.center, -forward).intersect_sphere(&sphere).unwrap(), 1.0);
+        assert!(Ray::new(Vector::empty(), -forward).intersect_sphere(&sphere).is_none());
+        assert!(Ray::new(Vector::new([2.0, 0.0, 0.0]), forward).intersect_sphere(&sphere).is_none());
+        // касание
+        assert_close(Ray::new(Vector::new([1.0, 0.0, 0.0]), forward).intersect_sphere(&sphere).unwrap(), 5.0);
+    }
+
+    #[test]
+    fn ray_unproject() {
+        let view_projection = Matrix::perspective(FRAC_PI_2, 1.0, 1.0, 10.0)
+            * Matrix::look_at(Vector::new([0.0, 0.0, 5.0]), Vector::empty(), Vector::new([0.0, 1.0, 0.0]));
+        let ray = Ray::unproject(0.0, 0.0, &view_projection.inverse().unwrap());
+        assert_vector_close(ray.origin, Vector::new([0.0, 0.0, 4.0]));
+        assert_vector_close(ray.dir, Vector::new([0.0, 0.0, -1.0]));
+        // правый верхний угол при fov 90 градусов - под 45 градусов к обеим осям
+        let ray = Ray::unproject(1.0, 1.0, &view_projection.inverse().unwrap());
+        assert_vector_close(ray.origin, Vector::new([1.0, 1.0, 4.0]));
+        assert_vector_close(ray.at(3.0_f32.sqrt()), Vector::new([2.0, 2.0, 3.0]));
+    }
+
+    // камера в (0, 0, 5) смотрит на начало координат, fov 90 градусов: видно z от 4 до -5,
+    // на расстоянии 5 (z = 0) кадр от -5 до 5 по x и y
+    fn frustum() -> Frustum {
+        Frustum::from_matrix(&(Matrix::perspective(FRAC_PI_2, 1.0, 1.0, 10.0)
+            * Matrix::look_at(Vector::new([0.0, 0.0, 5.0]), Vector::empty(), Vector::new([0.0, 1.0, 0.0]))))
+    }
+    fn cube(center: [f32; 3], half: f32) -> Aabb {
+        let center = Vector::new(center);
+        Aabb::new(center - Vector::new([half; 3]), center + Vector::new([half; 3]))
+    }
+
+    #[test]
+    fn frustum_planes() {
+        let frustum = frustum();
+        assert!(frustum.contains_point(Vector::empty()));
+        assert!(!frustum.contains_point(Vector::new([0.0, 0.0, 4.5])));
+        assert!(!frustum.contains_point(Vector::new([0.0, 0.0, -5.5])));
+        assert!(frustum.contains_point(Vector::new([4.9, 0.0, 0.0])));
+        assert!(!frustum.contains_point(Vector::new([0.0, 5.1, 0.0])));
+        // нормали внутрь, единичной длины: расстояние от центра до ближней плоскости - 4
+        let near = frustum.planes[4];
+        assert_close(near.normal.norm(), 1.0);
+        assert_close(near.distance(Vector::empty()), 4.0);
+    }
+
+    #[test]
+    fn frustum_culls_aabb() {
+        let frustum = frustum();
+        // перед камерой
+        assert!(frustum.intersects_aabb(&cube([0.0, 0.0, 0.0], 0.5)));
+        // позади камеры и за дальней плоскостью
+        assert!(!frustum.intersects_aabb(&cube([0.0, 0.0, 8.0], 0.5)));
+        assert!(!frustum.intersects_aabb(&cube([0.0, 0.0, -10.0], 0.5)));
+        // пересекает правую плоскость - остаётся, целиком правее - отсекается
+        assert!(frustum.intersects_aabb(&cube([5.0, 0.0, 0.0], 1.0)));
+        assert!(!frustum.intersects_aabb(&cube([8.0, 0.0, 0.0], 1.0)));
+        assert!(frustum.intersects_aabb(&cube([0.0, -5.0, 0.0], 1.0)));
+        // бокс больше всей пирамиды
+        assert!(frustum.intersects_aabb(&cube([0.0, 0.0, 0.0], 100.0)));
+    }
+
+    #[test]
+    fn frustum_culls_spheres() {
+        let frustum = frustum();
+        assert!(frustum.intersects_sphere(&Sphere::new(Vector::empty(), 0.5)));
+        assert!(!frustum.intersects_sphere(&Sphere::new(Vector::new([0.0, 0.0, 8.0]), 0.5)));
+        assert!(!frustum.intersects_sphere(&Sphere::new(Vector::new([0.0, 0.0, -10.0]), 0.5)));
+        // центр снаружи правой плоскости на 0.35, но радиус 1 до неё достаёт
+        assert!(frustum.intersects_sphere(&Sphere::new(Vector::new([5.5, 0.0, 0.0]), 1.0)));
+        assert!(!frustum.intersects_sphere(&Sphere::new(Vector::new([7.0, 0.0, 0.0]), 1.0)));
+        // ближняя плоскость в z = 4: сфера за ней, но касается
+        assert!(frustum.intersects_sphere(&Sphere::new(Vector::new([0.0, 0.0, 4.5]), 1.0)));
+    }
+
+    #[test]
+    fn aabb_union_with_empty() {
+        let aabb = cube([1.0, -2.0, 3.0], 0.5);
+        assert_eq!(Aabb::empty().union(&aabb), aabb);
+        assert_eq!(aabb.union(&Aabb::empty()), aabb);
+        assert!(Aabb::empty().union(&Aabb::empty()).is_empty());
+        assert_eq!(Aabb::empty().surface_area(), 0.0);
+    }
+
+    #[test]
+    fn bounds_of_single_point() {
+        let p = Vector::new([3.0, 4.0, 5.0]);
+        let aabb = Aabb::from_points(&[p]);
+        assert!(!aabb.is_empty());
+        assert_eq!(aabb, Aabb::new(p, p));
+        assert!(aabb.contains(p));
+        assert_eq!(aabb.surface_area(), 0.0);
+        assert_eq!(Sphere::from_points(&[p]), Sphere::new(p, 0.0));
+        assert_eq!(Sphere::from_points(&[p, p, p]), Sphere::new(p, 0.0));
+        assert_eq!(Sphere::from_points(&[]).radius, 0.0);
+    }
+
+    #[test]
+    fn ritter_sphere_contains_points() {
+        let mut rng = StdRng::seed_from_u64(35);
+        for _ in 0..100 {
+            let count = rng.random_range(1..50);
+            let scale = rng.random_range(0.01..100.0);
+            let points: Vec<Vector<3, f32>> = (0..count)
+                .map(|_| Vector::new([(); 3].map(|_| rng.random_range(-1.0..1.0) * scale)))
+                .collect();
+            let sphere = Sphere::from_points(&points);
+            let aabb = Aabb::from_points(&points);
+            for p in &points {
+                assert!((*p - sphere.center).norm() <= sphere.radius * (1.0 + EPS) + EPS * scale, "{p:?} outside {sphere:?}");
+                assert!(aabb.contains(*p));
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::{collections::{HashMap, HashSet}, hash::Hash};
+
+use sdl2::{event::{Event, WindowEvent}, keyboard::{Keycode, Mod}, mouse::MouseButton};
+
+/// Состояние ввода на текущий кадр. Scene::run вызывает handle_event для каждого события
+/// и begin_frame после каждого update, так что нажатие видит только первый update после него
+#[derive(Debug, Clone)]
+pub struct InputState {
+    events: Vec<Event>,
+    held: HashSet<Keycode>,
+    pressed: HashSet<Keycode>,
+    released: HashSet<Keycode>,
+    buttons_held: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    /// Модификаторы из последнего события клавиатуры
+    pub modifiers: Mod,
+    /// Положение курсора в пикселях окна
+    pub mouse: (i32, i32),
+    /// Сдвиг курсора за кадр
+    pub mouse_delta: (i32, i32),
+    /// Прокрутка колеса за кадр, > 0 - от себя
+    pub wheel: f32,
+    /// Новый размер окна, если оно менялось за кадр
+    pub resized: Option<(i32, i32)>,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        InputState {
+            events: vec![],
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            buttons_held: HashSet::new(),
+            buttons_pressed: HashSet::new(),
+            buttons_released: HashSet::new(),
+            modifiers: Mod::NOMOD,
+            mouse: (0, 0),
+            mouse_delta: (0, 0),
+            wheel: 0.0,
+            resized: None,
+        }
+    }
+}
+
+impl InputState {
+    /// Сбрасывает всё, что относится только к прошлому кадру; зажатые клавиши и кнопки остаются
+    pub fn begin_frame(&mut self) {
+        self.events.clear();
+        self.pressed.clear();
+        self.released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.mouse_delta = (0, 0);
+        self.wheel = 0.0;
+        self.resized = None;
+    }
+    pub fn handle_event(&mut self, event: Event) {
+        match &event {
+            Event::KeyDown { keycode: Some(key), keymod, repeat, .. } => {
+                self.modifiers = *keymod;
+                // автоповтор не считается новым нажатием
+                if !*repeat {
+                    self.pressed.insert(*key);
+                }
+                self.held.insert(*key);
+            },
+            Event::KeyUp { keycode: Some(key), keymod, .. } => {
+                self.modifiers = *keymod;
+                self.held.remove(key);
+                self.released.insert(*key);
+            },
+            Event::MouseMotion { x, y, xrel, yrel, .. } => {
+                self.mouse = (*x, *y);
+                self.mouse_delta = (self.mouse_delta.0 + xrel, self.mouse_delta.1 + yrel);
+            },
+            Event::MouseButtonDown { mouse_btn, x, y, .. } => {
+                self.mouse = (*x, *y);
+                self.buttons_held.insert(*mouse_btn);
+                self.buttons_pressed.insert(*mouse_btn);
+            },
+            Event::MouseButtonUp { mouse_btn, x, y, .. } => {
+                self.mouse = (*x, *y);
+                self.buttons_held.remove(mouse_btn);
+                self.buttons_released.insert(*mouse_btn);
+            },
+            Event::MouseWheel { precise_y, .. } => self.wheel += precise_y,
+            Event::Window { win_event: WindowEvent::Resized(w, h) | WindowEvent::SizeChanged(w, h), .. } => {
+                self.resized = Some((*w, *h));
+            },
+            // без фокуса отпускания клавиш не приходят, иначе они останутся зажатыми
+            Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                self.held.clear();
+                self.buttons_held.clear();
+            },
+            _ => {},
+        }
+        self.events.push(event);
+    }
+
+    /// События кадра в порядке поступления, кроме выхода
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+    pub fn is_held(&self, key: Keycode) -> bool {
+        self.held.contains(&key)
+    }
+    pub fn was_pressed(&self, key: Keycode) -> bool {
+        self.pressed.contains(&key)
+    }
+    pub fn was_released(&self, key: Keycode) -> bool {
+        self.released.contains(&key)
+    }
+    pub fn is_button_held(&self, button: MouseButton) -> bool {
+        self.buttons_held.contains(&button)
+    }
+    pub fn was_button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+    pub fn was_button_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+    /// Положение курсора в начале кадра
+    pub fn previous_mouse(&self) -> (i32, i32) {
+        (self.mouse.0 - self.mouse_delta.0, self.mouse.1 - self.mouse_delta.1)
+    }
+}
+
+/// Клавиша или кнопка мыши, на которую можно назначить действие.
+/// Для Key с модификаторами достаточно, чтобы был зажат любой из них (Mod::NOMOD - без условий)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Key(Keycode, Mod),
+    Button(MouseButton),
+}
+
+impl Binding {
+    pub fn key(key: Keycode) -> Binding {
+        Binding::Key(key, Mod::NOMOD)
+    }
+    fn matches_mod(modifiers: Mod, required: Mod) -> bool {
+        required.is_empty() || modifiers.intersects(required)
+    }
+    pub fn is_held(&self, input: &InputState) -> bool {
+        match *self {
+            Binding::Key(key, required) => input.is_held(key) && Binding::matches_mod(input.modifiers, required),
+            Binding::Button(button) => input.is_button_held(button),
+        }
+    }
+    pub fn was_pressed(&self, input: &InputState) -> bool {
+        match *self {
+            Binding::Key(key, required) => input.was_pressed(key) && Binding::matches_mod(input.modifiers, required),
+            Binding::Button(button) => input.was_button_pressed(button),
+        }
+    }
+}
+
+/// Назначения действий A на клавиши и кнопки, на одно действие может быть несколько
+#[derive(Debug, Clone)]
+pub struct Bindings<A> {
+    map: HashMap<A, Vec<Binding>>,
+}
+
+impl<A> Default for Bindings<A> {
+    fn default() -> Self {
+        Bindings { map: HashMap::new() }
+    }
+}
+
+impl<A: Copy + Eq + Hash> Bindings<A> {
+    pub fn new() -> Bindings<A> {
+        Bindings::default()
+    }
+    pub fn bind(&mut self, action: A, binding: Binding) -> &mut Self {
+        self.map.entry(action).or_default().push(binding);
+        self
+    }
+    pub fn unbind(&mut self, action: A) -> &mut Self {
+        self.map.remove(&action);
+        self
+    }
+    pub fn bindings(&self, action: A) -> &[Binding] {
+        self.map.get(&action).map_or(&[], |b| b.as_slice())
+    }
+    pub fn is_held(&self, input: &InputState, action: A) -> bool {
+        self.bindings(action).iter().any(|b| b.is_held(input))
+    }
+    pub fn was_pressed(&self, input: &InputState, action: A) -> bool {
+        self.bindings(action).iter().any(|b| b.was_pressed(input))
+    }
+    /// 1, -1 или 0 в зависимости от того, какое из двух действий зажато
+    pub fn axis(&self, input: &InputState, plus: A, minus: A) -> f32 {
+        self.is_held(input, plus) as i32 as f32 - self.is_held(input, minus) as i32 as f32
+    }
+}
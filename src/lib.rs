@@ -0,0 +1,11 @@
+// Части движка без SDL: геометрия, модели, текстуры и обработка кадра.
+// Бинарник подключает их отсюда, а тесты и бенчмарки собираются без окна
+pub mod bvh;
+pub mod deferred;
+pub mod font;
+pub mod geometry;
+pub mod model;
+pub mod overlay;
+pub mod postprocess;
+pub mod simd;
+pub mod tga;
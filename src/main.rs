@@ -1,107 +1,277 @@
 
-mod model;
-mod geometry;
+mod camera;
+mod debug;
+mod input;
 mod scene;
-mod tga;
-mod shader;
 
-use std::{any::Any, path::Path};
-use image::Delay;
-use sdl2::keyboard::{Keycode, Mod};
+use std::path::Path;
+use sdl2::{event::Event, keyboard::Keycode, mouse::MouseButton};
 
-use crate::{geometry::{ Matrix, Vector}, model::Model, scene::Scene};
+use rust_3d_engine::{deferred, geometry, model, overlay, postprocess, simd};
+
+use crate::{camera::{Camera, CameraAction}, input::Binding, debug::DebugDraw, deferred::Light, geometry::{Frustum, Matrix, Vector}, model::Model, postprocess::{Lut, Pass}, scene::{LoopSettings, PolygonMode, RenderPath, Scene, ViewMode}};
 
 const WIDTH: usize = 1024;
 const HEIGHT: usize = 1024;
 const DEPTH: usize = 255;
-const SIZE: f32 = 2.0;
+const FOV: f32 = std::f32::consts::FRAC_PI_4;
+/// Доля ширины и высоты кадра, оставляемая пустой с каждой стороны
+const VIEWPORT_MARGIN: f32 = 1.0 / 8.0;
+const CHUNK_SIZE: usize = 256;
+const FIXED_STEP: f32 = 1.0 / 120.0;
+const MAX_FPS: f32 = 60.0;
+/// Непрозрачность модели в режиме рентгена
+const XRAY_ALPHA: f32 = 0.4;
+/// Переключатели проходов постобработки: FXAA, bloom, виньетка, резкость, глубина резкости, цветокоррекция
+const POST_KEYS: [Keycode; 6] = [Keycode::F5, Keycode::F6, Keycode::F7, Keycode::F8, Keycode::F9, Keycode::F10];
 
 
 
 fn main() {
     let mut model = Model::read(Path::new("obj/head.obj"));
-    model.read_texture(Path::new("obj/head.tga"));
+    let texture = Path::new("obj/head.tga");
+    model.read_texture(texture).unwrap_or_else(|err| panic!("{}: {err}", texture.display()));
+    model.normalize_to_unit_cube();
     // let light_dir = Vector3D::new(1.0, -1.0, 1.0).normalize(1.0);
 
     let light_dir: Vector<3,f32> = Vector::new([1., -1., 1.]).normalize(1.);
-    let eye: Vector<3,f32> = Vector::new([1.0, 1.0, 3.0]);
-    let center: Vector<3,f32> = Vector::new([0.0, 0.0, 0.0]);
+    let view_dir: Vector<3,f32> = Vector::new([-1.0, -1.0, -3.0]);
     let up: Vector<3,f32> = Vector::new([0.0, 1.0, 0.0]);
 
-    
-    let model_view: Matrix<4, 4> = look_at(eye, center, up);
-    let viewport: Matrix<4, 4> = viewport((WIDTH/8) as i32, (HEIGHT/8) as i32, (WIDTH*3/4) as i32, (HEIGHT*3/4) as i32);
-    let projection: Matrix<4, 4> = projection(-1./(eye-center).norm());
-    let light_dir = (projection.clone() * model_view.clone() * light_dir.embed::<4>(0.0)).proj::<3>().normalize(1.);
+    let sphere = model.bounding_sphere();
+    let mut camera = Camera::framing(&sphere, view_dir, up, FOV);
+    camera.set_viewport_size(WIDTH, HEIGHT);
+    // средняя кнопка в просмотрщике прячет HUD, сдвиг остаётся только на правой
+    camera.bindings
+        .unbind(CameraAction::Pan)
+        .bind(CameraAction::Pan, Binding::Button(MouseButton::Right));
 
-    
+    // направленный свет, как его видела бы теневая карта: ортографическая проекция вокруг модели
+    let light_pos = sphere.center - light_dir * (sphere.radius * 2.0);
+    let light_view_projection = Matrix::orthographic(-sphere.radius, sphere.radius, -sphere.radius, sphere.radius, sphere.radius, sphere.radius * 3.0)
+        * Matrix::look_at(light_pos, sphere.center, up);
 
     let mut scene = Scene::new(WIDTH, HEIGHT, DEPTH);
+    // порядок совпадает с клавишами F5-F10, все проходы выключены до нажатия
+    scene.post
+        .push(Pass::Fxaa)
+        .push(Pass::Bloom { threshold: 0.8, intensity: 0.6, radius: 8 })
+        .push(Pass::Vignette { strength: 0.5, radius: 0.6 })
+        .push(Pass::Sharpen { amount: 0.5 })
+        .push(Pass::DepthOfField { focus: camera.distance, range: sphere.radius, radius: 4 })
+        // тёплый тон: красный чуть выше, синий чуть ниже
+        .push(Pass::ColorGrade(Lut::from_fn(16, |c| Vector::new([c.x().powf(0.9), c.y(), c.z().powf(1.1)]))));
+    for pass in &mut scene.post.passes {
+        pass.enabled = false;
+    }
 
-    scene.wait_for_exit(|scene: &mut Scene, keycodes| {
-
-
-        let mut shader = Shader::new(&model, &projection, &model_view);
-        let faces = &model.faces;
+    let bounds = model.aabb();
+    let chunks = model.chunks(CHUNK_SIZE);
 
-        for i in 0..faces.len() {
-            for j in 0..3 {
-                shader.vertex(i, j);
+    // cargo run -- frame.png: при выходе последний кадр с HUD сохраняется в frame.png
+    let screenshot = std::env::args().nth(1);
+    let settings = LoopSettings {
+        fixed_step: Some(FIXED_STEP),
+        max_fps: Some(MAX_FPS),
+        redraw_on_change: true,
+    };
+    let mut state = View {
+        camera,
+        polygon_mode: PolygonMode::Fill,
+        view_mode: ViewMode::Color,
+        debug_draw: DebugDraw::default(),
+        msaa: 1,
+        ssaa: 1,
+        resolution_scale: 1.0,
+        post: [false; POST_KEYS.len()],
+        render_path: RenderPath::Forward,
+        compare: false,
+        hud: true,
+        xray: false,
+    };
+    scene.run(settings, &mut state, |View { camera, polygon_mode, view_mode, debug_draw, msaa, ssaa, resolution_scale, post, render_path, compare, hud, xray }, input, time| {
+        let before = camera.model_view();
+        if let Some((width, height)) = input.resized {
+            camera.set_viewport_size(width as usize, height as usize);
+        }
+        camera.handle_input(input, time.dt);
+        // двойной щелчок - исходный вид; число щелчков есть только в самих событиях
+        if input.events().iter().any(|event| matches!(event, Event::MouseButtonDown { mouse_btn: MouseButton::Left, clicks: 2, .. })) {
+            camera.reset();
+        }
+        // пока зажат пробел, кадр рисуется без постобработки
+        if input.was_pressed(Keycode::Space) || input.was_released(Keycode::Space) {
+            *compare = input.is_held(Keycode::Space);
+            return true;
+        }
+        if input.was_button_released(MouseButton::Middle) {
+            *hud = !*hud;
+            return true;
+        }
+        // F - заливка, рёбра, заливка с рёбрами
+        if input.was_pressed(Keycode::F) {
+            *polygon_mode = match polygon_mode {
+                PolygonMode::Fill => PolygonMode::Line,
+                PolygonMode::Line => PolygonMode::FillAndLine,
+                PolygonMode::FillAndLine => PolygonMode::Fill,
+            };
+            return true;
+        }
+        // V - следующий режим отображения буферов
+        if input.was_pressed(Keycode::V) {
+            *view_mode = view_mode.next();
+            return true;
+        }
+        // M - MSAA 1x, 2x, 4x, 8x (в отложенном режиме выключен); N - SSAA 2x2 вкл/выкл
+        if input.was_pressed(Keycode::M) {
+            *msaa = if *msaa >= 8 { 1 } else { *msaa * 2 };
+            return true;
+        }
+        if input.was_pressed(Keycode::N) {
+            *ssaa = 3 - *ssaa;
+            return true;
+        }
+        // [ и ] - буферы вдвое меньше или больше, от четверти окна до окна
+        if input.was_pressed(Keycode::LeftBracket) || input.was_pressed(Keycode::RightBracket) {
+            let factor = if input.was_pressed(Keycode::RightBracket) { 2.0 } else { 0.5 };
+            *resolution_scale = (*resolution_scale * factor).clamp(0.25, 1.0);
+            return true;
+        }
+        // G - прямое или отложенное освещение
+        if input.was_pressed(Keycode::G) {
+            *render_path = match render_path {
+                RenderPath::Forward => RenderPath::Deferred,
+                RenderPath::Deferred => RenderPath::Forward,
+            };
+            return true;
+        }
+        // 1-4 - нормали, боксы, оси, свет; X - полупрозрачная модель
+        let toggles = [
+            (Keycode::Num1, &mut debug_draw.normals),
+            (Keycode::Num2, &mut debug_draw.bounds),
+            (Keycode::Num3, &mut debug_draw.axes),
+            (Keycode::Num4, &mut debug_draw.lights),
+            (Keycode::X, xray),
+        ];
+        let post_toggles = POST_KEYS.into_iter().zip(post.iter_mut());
+        let mut changed = false;
+        for (key, flag) in toggles.into_iter().chain(post_toggles) {
+            if input.was_pressed(key) {
+                *flag = !*flag;
+                changed = true;
             }
-            scene.triangle(&shader.varing_tri,  &shader, &viewport);
         }
-    });
-}
-
-fn viewport(x: i32, y: i32, w: i32, h: i32) -> Matrix<4, 4> {
-    let mut m: Matrix<4, 4> = Matrix::identity();
-    m[0][3] = (x + w) as f32 / 2.0;
-    m[1][3] = (y + h) as f32 / 2.0;
-    m[2][3] = DEPTH as f32 / 2.0;
+        changed || camera.model_view() != before
+    }, |View { camera, polygon_mode, view_mode, debug_draw, msaa, ssaa, resolution_scale, post, render_path, compare, hud, xray }, scene, time| {
+        scene.set_msaa(*msaa);
+        if scene.ssaa() != *ssaa {
+            scene.set_ssaa(*ssaa);
+        }
+        if scene.resolution_scale() != *resolution_scale {
+            scene.set_resolution_scale(*resolution_scale);
+        }
+        scene.polygon_mode = *polygon_mode;
+        scene.view_mode = *view_mode;
+        scene.clip_planes = (camera.near, camera.far);
+        for (pass, enabled) in scene.post.passes.iter_mut().zip(post) {
+            pass.enabled = *enabled && !*compare;
+            // фокус на точке, вокруг которой вращается камера
+            if let Pass::DepthOfField { focus, .. } = &mut pass.pass {
+                *focus = camera.distance;
+            }
+        }
+        let model_view = camera.model_view();
+        let projection = camera.projection();
+        let viewport = scene.viewport(VIEWPORT_MARGIN, DEPTH as f32);
+        scene.render_path = *render_path;
+        scene.unproject = (&viewport * &projection).inverse().unwrap();
+        // направленный свет и точечный у камеры, в пространстве камеры
+        scene.lights = vec![
+            Light::Directional { direction: (&model_view * light_dir.embed::<4>(0.0)).xyz(), color: Vector::new([1.0, 0.95, 0.9]) },
+            Light::Point { position: Vector::new([0.0, 0.0, 0.0]), color: Vector::new([0.3, 0.3, 0.4]), range: camera.distance * 2.0 },
+        ];
+        let mvp = simd::mul(&projection, &model_view);
+        let frustum = Frustum::from_matrix(&mvp);
 
-    m[0][0] = w as f32 / 2.0;
-    m[1][1] = h as f32 / 2.0;
-    m[2][2] = DEPTH as f32 / 2.0;
+        // прозрачная модель всегда идёт прямым путём поверх освещённого кадра
+        scene.alpha = if *xray { XRAY_ALPHA } else { 1.0 };
+        if scene.stats.cull_model(frustum.intersects_aabb(&bounds)) {
+            let mut shader = Shader::new(&model, &projection, &model_view);
 
-    m
-}
+            for chunk in &chunks {
+                if !scene.stats.cull_chunk(frustum.intersects_aabb(&chunk.aabb)) {
+                    continue;
+                }
+                for i in chunk.faces.clone() {
+                    for j in 0..3 {
+                        shader.vertex(i, j);
+                    }
+                    scene.triangle(&shader.varing_tri,  &shader, &viewport);
+                }
+            }
+        }
 
-fn look_at(_eye: Vector<3, f32>, _center: Vector<3, f32>, _up: Vector<3, f32>) -> Matrix<4, 4> {
-    let z  =  (_eye - _center).normalize(1.0);
-    let x =  cross(_up, z).normalize(1.0);
-    let y =  cross(z, x).normalize(1.0);
+        if debug_draw.normals {
+            debug::normals(scene, &mvp, &viewport, &model, debug_draw.normal_length, debug::NORMAL);
+        }
+        if debug_draw.bounds {
+            debug::aabb(scene, &mvp, &viewport, &bounds, debug::BOUNDS);
+            for chunk in &chunks {
+                debug::aabb(scene, &mvp, &viewport, &chunk.aabb, debug::BOUNDS & 0x7F7F7F);
+            }
+        }
+        if debug_draw.axes {
+            debug::axes(scene, &mvp, &viewport, Vector::empty(), 1.0);
+        }
+        if debug_draw.lights {
+            debug::light(scene, &mvp, &viewport, light_pos, Some(light_dir), sphere.radius * 0.5, debug::LIGHT);
+            debug::frustum(scene, &mvp, &viewport, &light_view_projection, debug::FRUSTUM);
+        }
 
-    let mut minv: Matrix<4, 4> = Matrix::identity();
-    let mut tr: Matrix<4, 4> = Matrix::identity();
+        if !*hud {
+            return;
+        }
+        let stats = scene.stats;
+        let eye = camera.eye();
+        let hud = format!("{:.0} fps  frame {}\ntriangles {}  chunks culled {}/{}\ncamera {:.2} {:.2} {:.2}  {:?}  {:?}  {:?}\nMSAA {}x  SSAA {}x  buffers {}x{}  post {}  {:?}",
+            1.0 / time.dt.max(f32::EPSILON), time.frame, stats.triangles, stats.chunks_culled, stats.chunks_tested,
+            eye.x(), eye.y(), eye.z(), camera.mode, polygon_mode, view_mode, scene.msaa(), ssaa * ssaa, scene.width(), scene.height(),
+            post.iter().map(|&on| if on { '+' } else { '-' }).collect::<String>(), render_path);
+        scene.overlay.label(8, 8, &hud, overlay::WHITE);
+    });
 
-    for i in 0..3 {
-        minv[0][i] = x[i];
-        minv[1][i] = y[i];
-        minv[2][i] = z[i];
-        tr[i][3] = -_center[i];
+    if let Some(path) = screenshot {
+        scene.to_image().save(&path).unwrap_or_else(|err| panic!("{path}: {err}"));
     }
-
-    minv * tr
 }
 
-fn cross(v1: Vector<3, f32>, v2: Vector<3, f32>) -> Vector<3, f32> {
-    Vector::new([
-        v1[1] * v2[2] - v1[2] * v2[1],
-        -(v1[0] * v2[2] - v1[2] * v2[0]),
-        v1[0] * v2[1] - v1[1] * v2[0]
-    ])
-}
-fn projection(coef: f32) -> Matrix<4, 4>{
-    let mut result: Matrix<4, 4> = Matrix::identity();
-    result[3][2] = coef;
-    result
+/// Состояние просмотра, которое меняется вводом
+struct View {
+    camera: Camera,
+    polygon_mode: PolygonMode,
+    view_mode: ViewMode,
+    debug_draw: DebugDraw,
+    msaa: usize,
+    ssaa: usize,
+    /// Размер буферов относительно окна
+    resolution_scale: f32,
+    post: [bool; POST_KEYS.len()],
+    render_path: RenderPath,
+    /// Постобработка временно выключена для сравнения
+    compare: bool,
+    hud: bool,
+    xray: bool,
 }
 
 struct Shader<'a> {
     varing_uv: Matrix<2, 3>,
+    varing_norm: Matrix<3, 3>,
     varing_tri: Matrix<4, 3>,
+    /// Номер материала в Scene::materials
+    pub material: u16,
     model: &'a Model,
-    projection: &'a Matrix<4,4>,
+    /// projection * model_view, собранная один раз на модель
+    mvp: simd::SimdMatrix,
     model_view: &'a Matrix<4,4>,
 }
 
@@ -109,22 +279,36 @@ impl<'a>  Shader<'_> {
     pub fn new(model: &'a Model, projection: &'a Matrix<4,4>, model_view: &'a Matrix<4,4>) -> Shader<'a> {
         Shader {
             varing_uv: Matrix::new(),
+            varing_norm: Matrix::new(),
             varing_tri: Matrix::new(),
+            material: 0,
             model,
-            projection,
+            mvp: simd::SimdMatrix::from(&simd::mul(projection, model_view)),
             model_view
         }
     }
     
     pub fn vertex(&mut self, iface: usize, nthvert: usize) -> Vector<4,f32> {
-        self.varing_uv.set_col(nthvert, self.model.uv(iface, nthvert).cast());
-        let gl_vertex: Vector<4, f32> = self.projection.clone() * self.model_view.clone() * self.model.vert(iface, nthvert).embed::<4>(1.0);
+        self.varing_uv.set_col(nthvert, self.model.uv(iface, nthvert));
+        self.varing_norm.set_col(nthvert, self.model.norm(iface, nthvert));
+        let gl_vertex: Vector<4, f32> = simd::transform(&self.mvp, self.model.vert(iface, nthvert).embed::<4>(1.0));
         self.varing_tri.set_col(nthvert, gl_vertex);
         gl_vertex
     }   
     pub fn fragment(&self, bar: Vector<3, f32>, color: &mut u32) -> bool {
-        let uv = self.varing_uv.clone() * bar;
+        let uv = &self.varing_uv * bar;
         *color = self.model.diffuse(uv);
         false
     }
+    pub fn uv(&self, bar: Vector<3, f32>) -> Vector<2, f32> {
+        &self.varing_uv * bar
+    }
+    /// Нормаль в пространстве модели
+    pub fn normal(&self, bar: Vector<3, f32>) -> Vector<3, f32> {
+        (&self.varing_norm * bar).normalize(1.0)
+    }
+    /// Нормаль в пространстве камеры; model_view без масштаба, так что хватает её самой
+    pub fn view_normal(&self, bar: Vector<3, f32>) -> Vector<3, f32> {
+        (self.model_view * self.normal(bar).embed::<4>(0.0)).xyz().normalize(1.0)
+    }
 }
\ No newline at end of file
@@ -1,8 +1,22 @@
-use std::{fs::{self, File}, io::{BufRead, BufReader, Read}, path::Path};
+use std::{fs::File, io::{BufRead, BufReader}, ops::Range, path::Path};
 
-use image::{GenericImageView, ImageBuffer};
+use crate::{geometry::{Aabb, Matrix, Ray, Sphere, Vector}, tga::{Tga, TgaError}};
 
-use crate::{geometry::{Vector}, tga::Tga};
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub face: usize,
+    /// Номера вершин грани, образующих треугольник (многоугольники разбиваются веером)
+    pub corners: [usize; 3],
+    pub bar: Vector<3, f32>,
+    pub t: f32,
+}
+
+/// Непрерывный диапазон граней модели со своим боксом, единица отсечения по пирамиде видимости
+#[derive(Debug, Clone)]
+pub struct MeshChunk {
+    pub faces: Range<usize>,
+    pub aabb: Aabb,
+}
 
 #[derive(Clone)]
 pub struct Model {
@@ -81,35 +95,196 @@ impl Model {
             texture: None,
         }
     }
-    pub fn read_texture(&mut self, path: &Path) {
-        self.texture = Some(Tga::read_file(path));
-        
+    pub fn read_texture(&mut self, path: &Path) -> Result<(), TgaError> {
+        self.texture = Some(Tga::read_file(path)?);
+        Ok(())
     }
-    pub fn diffuse(&self, mut uvf: Vector<2, f32>) -> u32 {
+    pub fn diffuse(&self, uvf: Vector<2, f32>) -> u32 {
         let texture = self.texture.as_ref().unwrap();
 
-        let uv = Vector::new([uvf[0] * texture.width() as f32, uvf[1] * texture.height() as f32]).cast::<i32>();
-        texture.get_pixel(uv[0], uv[1])
+        let x = (uvf.x() * texture.width() as f32) as i32;
+        let y = (uvf.y() * texture.height() as f32) as i32;
+        texture.get_pixel(x.clamp(0, texture.width() as i32 - 1), y.clamp(0, texture.height() as i32 - 1))
     }
-    pub fn uv(&self, iface: usize, nvert: usize) -> Vector<2, i32> {
-        let idx = self.faces[iface as usize][nvert as usize][1] as usize;
-        let texture = self.texture.as_ref().unwrap();
-        Vector::new([
-            self.uv[idx][0] * texture.width() as f32,  
-            self.uv[idx][1] * texture.height() as f32
-        ]).cast()
-
+    pub fn uv(&self, iface: usize, nvert: usize) -> Vector<2, f32> {
+        let idx = self.faces[iface][nvert][1] as usize;
+        self.uv[idx]
     }
     pub fn norm(&self, iface: usize, nvert: usize) -> Vector<3,f32>{
-        let idx = self.faces[iface as usize][nvert as usize][2];
+        let idx = self.faces[iface][nvert][2];
         self.norms[idx as usize].normalize(1.0)
     }
     pub fn vert(&self, iface: usize, nvert: usize) -> Vector<3,f32> {
         self.verticates[self.faces[iface][nvert][0] as usize]
     }
+    /// Треугольники всех граней: (номер грани, номера её вершин); многоугольники разбиваются веером
+    pub fn triangles(&self) -> impl Iterator<Item = (usize, [usize; 3])> + '_ {
+        self.faces.iter().enumerate().flat_map(|(iface, face)| {
+            (1..face.len().saturating_sub(1)).map(move |k| (iface, [0, k, k + 1]))
+        })
+    }
+    pub fn triangle(&self, iface: usize, corners: [usize; 3]) -> [Vector<3, f32>; 3] {
+        corners.map(|nvert| self.vert(iface, nvert))
+    }
+    /// Ближайшее пересечение луча с моделью перебором всех треугольников
+    pub fn raycast(&self, ray: &Ray) -> Option<RayHit> {
+        let mut nearest: Option<RayHit> = None;
+        for (face, corners) in self.triangles() {
+            let [a, b, c] = self.triangle(face, corners);
+            if let Some((t, bar)) = ray.intersect_triangle(a, b, c) && nearest.is_none_or(|hit| t < hit.t) {
+                nearest = Some(RayHit { face, corners, bar, t });
+            }
+        }
+        nearest
+    }
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points(&self.verticates)
+    }
+    pub fn bounding_sphere(&self) -> Sphere {
+        Sphere::from_points(&self.verticates)
+    }
+    /// Делит грани на куски по faces_per_chunk подряд идущих граней; в OBJ соседние грани
+    /// обычно лежат рядом, так что боксы кусков получаются компактными
+    pub fn chunks(&self, faces_per_chunk: usize) -> Vec<MeshChunk> {
+        (0..self.faces.len()).step_by(faces_per_chunk.max(1))
+            .map(|start| {
+                let faces = start..(start + faces_per_chunk.max(1)).min(self.faces.len());
+                let mut aabb = Aabb::empty();
+                for iface in faces.clone() {
+                    for nvert in 0..self.faces[iface].len() {
+                        aabb.extend(self.vert(iface, nvert));
+                    }
+                }
+                MeshChunk { faces, aabb }
+            })
+            .collect()
+    }
+    /// Применяет матрицу к вершинам, нормали преобразуются обратной транспонированной.
+    /// Паникует на вырожденной матрице: она сплющивает модель, и нормали для неё не определены
+    pub fn transform(&mut self, m: &Matrix<4, 4>) {
+        let normal_matrix = m.inverse()
+            .unwrap_or_else(|| panic!("Model::transform: singular matrix {m:?}, normals are undefined"))
+            .transpose();
+        for v in self.verticates.iter_mut() {
+            let p = m * v.embed::<4>(1.0);
+            *v = p.xyz() / p.w();
+        }
+        for n in self.norms.iter_mut() {
+            *n = (&normal_matrix * n.embed::<4>(0.0)).xyz().normalize(1.0);
+        }
+    }
+    /// Переносит модель так, чтобы центр её AABB оказался в начале координат
+    pub fn center(&mut self) {
+        let center = self.aabb().center();
+        self.transform(&Matrix::translation(-center));
+    }
+    /// Центрирует модель и равномерно масштабирует её в куб [-1, 1]
+    pub fn normalize_to_unit_cube(&mut self) {
+        let aabb = self.aabb();
+        let size = aabb.size();
+        let extent = size.x().max(size.y()).max(size.z());
+        if aabb.is_empty() || extent <= 0.0 {
+            return;
+        }
+        let scale = 2.0 / extent;
+        self.transform(&(Matrix::scaling(Vector::new([scale; 3])) * Matrix::translation(-aabb.center())));
+    }
 }
 impl std::fmt::Debug for Model {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Model").field("verticates", &self.verticates).field("faces", &self.faces).finish()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube() -> Model {
+        Model::read(&Path::new(env!("CARGO_MANIFEST_DIR")).join("obj/cube.obj"))
+    }
+
+    #[test]
+    fn raycast_cube() {
+        let model = cube();
+        // передняя грань z = 1 - пятиугольник, разбитый веером
+        let hit = model.raycast(&Ray::new(Vector::new([-0.5, -0.5, 5.0]), Vector::new([0.0, 0.0, -1.0]))).unwrap();
+        assert_eq!(hit.face, 0);
+        assert!((hit.t - 4.0).abs() < 1e-5);
+        let [a, b, c] = model.triangle(hit.face, hit.corners);
+        let point = a * hit.bar[0] + b * hit.bar[1] + c * hit.bar[2];
+        assert!((point - Vector::new([-0.5, -0.5, 1.0])).norm() < 1e-5);
+
+        // правая грань x = 1
+        let hit = model.raycast(&Ray::new(Vector::new([5.0, -0.5, -0.5]), Vector::new([-1.0, 0.0, 0.0]))).unwrap();
+        assert_eq!(hit.face, 5);
+        assert!((hit.t - 4.0).abs() < 1e-5);
+
+        // изнутри - срезанный угол x + y + z = 2
+        let diagonal = Vector::new([1.0, 1.0, 1.0]).normalize(1.0);
+        let hit = model.raycast(&Ray::new(Vector::new([0.0, 0.0, 0.0]), diagonal)).unwrap();
+        assert_eq!(hit.face, 1);
+        assert!((hit.t - 2.0 / 3.0_f32.sqrt()).abs() < 1e-5);
+
+        // мимо и от модели
+        assert!(model.raycast(&Ray::new(Vector::new([0.0, 0.0, 5.0]), Vector::new([0.0, 0.0, 1.0]))).is_none());
+        assert!(model.raycast(&Ray::new(Vector::new([5.0, 5.0, 5.0]), Vector::new([1.0, 0.0, 0.0]))).is_none());
+    }
+
+    #[test]
+    fn triangles_fan() {
+        let model = cube();
+        // пятиугольники дают по три треугольника, четырёхугольники - по два
+        let counts: Vec<usize> = (0..model.faces.len()).map(|f| model.triangles().filter(|(face, _)| *face == f).count()).collect();
+        assert_eq!(counts, [3, 1, 2, 2, 3, 3, 2]);
+        assert!(model.triangles().all(|(_, [a, b, c])| a == 0 && b + 1 == c));
+    }
+
+    fn assert_aabb_close(aabb: &Aabb, min: [f32; 3], max: [f32; 3]) {
+        for i in 0..3 {
+            assert!((aabb.min[i] - min[i]).abs() < 1e-5 && (aabb.max[i] - max[i]).abs() < 1e-5, "{aabb:?}");
+        }
+    }
+
+    #[test]
+    fn normalize_to_unit_cube() {
+        let mut model = cube();
+        // нормали граней x = 1 и z = -1 должны пережить масштаб в миллиметры и обратно
+        model.norms = vec![Vector::new([1.0, 0.0, 0.0]), Vector::new([0.0, 0.0, -1.0])];
+        model.transform(&(Matrix::translation(Vector::new([0.01, -0.005, 0.002])) * Matrix::scaling(Vector::new([1e-3; 3]))));
+        assert_aabb_close(&model.aabb(), [0.009, -0.006, 0.001], [0.011, -0.004, 0.003]);
+
+        model.normalize_to_unit_cube();
+        assert_aabb_close(&model.aabb(), [-1.0; 3], [1.0; 3]);
+        assert!((model.norms[0] - Vector::new([1.0, 0.0, 0.0])).norm() < 1e-5, "{:?}", model.norms);
+        assert!((model.norms[1] - Vector::new([0.0, 0.0, -1.0])).norm() < 1e-5, "{:?}", model.norms);
+
+        // вытянутая модель: самая длинная сторона становится 2, пропорции сохраняются
+        model.transform(&Matrix::scaling(Vector::new([4.0, 1.0, 2.0])));
+        model.normalize_to_unit_cube();
+        assert_aabb_close(&model.aabb(), [-1.0, -0.25, -0.5], [1.0, 0.25, 0.5]);
+
+        model.transform(&Matrix::translation(Vector::new([3.0, 0.0, 0.0])));
+        model.center();
+        assert_aabb_close(&model.aabb(), [-1.0, -0.25, -0.5], [1.0, 0.25, 0.5]);
+    }
+
+    #[test]
+    fn normalize_single_point() {
+        let mut model = cube();
+        model.verticates.fill(Vector::new([3.0, 4.0, 5.0]));
+        // размер нулевой, масштабировать не во что - модель не меняется
+        model.normalize_to_unit_cube();
+        assert_aabb_close(&model.aabb(), [3.0, 4.0, 5.0], [3.0, 4.0, 5.0]);
+        model.center();
+        assert_aabb_close(&model.aabb(), [0.0; 3], [0.0; 3]);
+        let sphere = model.bounding_sphere();
+        assert_eq!(sphere.radius, 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "singular matrix")]
+    fn transform_singular() {
+        cube().transform(&Matrix::scaling(Vector::new([1.0, 0.0, 1.0])));
+    }
+}
@@ -0,0 +1,252 @@
+use image::{Rgba, RgbaImage};
+
+use crate::font::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+// Цвета оверлея - 0xAARRGGBB, альфа 0xFF - непрозрачный
+pub const WHITE: u32 = 0xFFFF_FFFF;
+pub const BLACK: u32 = 0xFF00_0000;
+pub const RED: u32 = 0xFFFF_0000;
+pub const GREEN: u32 = 0xFF00_FF00;
+pub const BLUE: u32 = 0xFF00_00FF;
+pub const YELLOW: u32 = 0xFFFF_FF00;
+/// Полупрозрачная подложка под текст
+pub const SHADOW: u32 = 0x8000_0000;
+
+/// Куда рисуется оверлей. Координаты от левого верхнего угла, цвета 0xRRGGBB
+pub trait PixelTarget {
+    fn size(&self) -> (usize, usize);
+    fn get_pixel(&self, x: usize, y: usize) -> u32;
+    fn set_pixel(&mut self, x: usize, y: usize, color: u32);
+}
+
+impl PixelTarget for RgbaImage {
+    fn size(&self) -> (usize, usize) {
+        (self.width() as usize, self.height() as usize)
+    }
+    fn get_pixel(&self, x: usize, y: usize) -> u32 {
+        let [r, g, b, _] = self.get_pixel(x as u32, y as u32).0;
+        (r as u32) << 16 | (g as u32) << 8 | b as u32
+    }
+    fn set_pixel(&mut self, x: usize, y: usize, color: u32) {
+        self.put_pixel(x as u32, y as u32, Rgba([(color >> 16) as u8, (color >> 8) as u8, color as u8, 255]));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Text { x: i32, y: i32, text: String, color: u32, scale: usize },
+    Rect { x: i32, y: i32, width: i32, height: i32, color: u32, filled: bool },
+    Line { x0: i32, y0: i32, x1: i32, y1: i32, color: u32 },
+}
+
+/// Двумерный слой поверх кадра: фигуры копятся за кадр и рисуются после 3D
+#[derive(Debug, Clone, Default)]
+pub struct Overlay {
+    shapes: Vec<Shape>,
+}
+
+impl Overlay {
+    pub fn new() -> Overlay {
+        Overlay::default()
+    }
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+    }
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+    pub fn shapes(&self) -> &[Shape] {
+        &self.shapes
+    }
+    /// Текст с левым верхним углом в (x, y); '\n' переводит строку
+    pub fn text(&mut self, x: i32, y: i32, text: &str, color: u32) {
+        self.text_scaled(x, y, text, color, 1);
+    }
+    /// Текст, каждый пиксель шрифта которого - квадрат scale x scale
+    pub fn text_scaled(&mut self, x: i32, y: i32, text: &str, color: u32, scale: usize) {
+        self.shapes.push(Shape::Text { x, y, text: text.to_string(), color, scale: scale.max(1) });
+    }
+    /// Текст на полупрозрачной подложке, чтобы читался на любом фоне
+    pub fn label(&mut self, x: i32, y: i32, text: &str, color: u32) {
+        let (width, height) = font::text_size(text, 1);
+        self.fill_rect(x - 2, y - 2, width as i32 + 4, height as i32 + 4, SHADOW);
+        self.text(x, y, text, color);
+    }
+    pub fn rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: u32) {
+        self.shapes.push(Shape::Rect { x, y, width, height, color, filled: false });
+    }
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: u32) {
+        self.shapes.push(Shape::Rect { x, y, width, height, color, filled: true });
+    }
+    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
+        self.shapes.push(Shape::Line { x0, y0, x1, y1, color });
+    }
+
+    /// Рисует все фигуры в порядке добавления
+    pub fn draw(&self, target: &mut impl PixelTarget) {
+        for shape in &self.shapes {
+            match shape {
+                Shape::Text { x, y, text, color, scale } => draw_text(target, *x, *y, text, *color, *scale),
+                Shape::Rect { x, y, width, height, color, filled: true } => {
+                    for py in *y..y + height {
+                        for px in *x..x + width {
+                            blend(target, px, py, *color);
+                        }
+                    }
+                },
+                Shape::Rect { x, y, width, height, color, filled: false } => {
+                    if *width <= 0 || *height <= 0 {
+                        continue;
+                    }
+                    let (x1, y1) = (x + width - 1, y + height - 1);
+                    for px in *x..=x1 {
+                        blend(target, px, *y, *color);
+                        if y1 != *y {
+                            blend(target, px, y1, *color);
+                        }
+                    }
+                    for py in y + 1..y1 {
+                        blend(target, *x, py, *color);
+                        if x1 != *x {
+                            blend(target, x1, py, *color);
+                        }
+                    }
+                },
+                Shape::Line { x0, y0, x1, y1, color } => draw_line(target, *x0, *y0, *x1, *y1, *color),
+            }
+        }
+    }
+}
+
+fn draw_text(target: &mut impl PixelTarget, x: i32, y: i32, text: &str, color: u32, scale: usize) {
+    let scale = scale as i32;
+    for (row, line) in text.lines().enumerate() {
+        let top = y + row as i32 * GLYPH_HEIGHT as i32 * scale;
+        for (column, c) in line.chars().enumerate() {
+            let left = x + column as i32 * GLYPH_WIDTH as i32 * scale;
+            for (gy, bits) in font::glyph(c).iter().enumerate() {
+                for gx in 0..GLYPH_WIDTH {
+                    if bits >> gx & 1 == 0 {
+                        continue;
+                    }
+                    for sy in 0..scale {
+                        for sx in 0..scale {
+                            blend(target, left + gx as i32 * scale + sx, top + gy as i32 * scale + sy, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Брезенхем
+fn draw_line(target: &mut impl PixelTarget, mut x0: i32, mut y0: i32, x1: i32, y1: i32, color: u32) {
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx = if x0 < x1 { 1 } else { -1 };
+    let sy = if y0 < y1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    loop {
+        blend(target, x0, y0, color);
+        if x0 == x1 && y0 == y1 {
+            break;
+        }
+        let e2 = 2 * error;
+        if e2 >= dy {
+            error += dy;
+            x0 += sx;
+        }
+        if e2 <= dx {
+            error += dx;
+            y0 += sy;
+        }
+    }
+}
+
+/// Смешивает ARGB-цвет с пикселем цели, точки за её пределами пропускаются
+fn blend(target: &mut impl PixelTarget, x: i32, y: i32, color: u32) {
+    let (width, height) = target.size();
+    if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+        return;
+    }
+    let (x, y) = (x as usize, y as usize);
+    let alpha = color >> 24;
+    if alpha == 0xFF {
+        target.set_pixel(x, y, color & 0xFF_FFFF);
+        return;
+    }
+    let dst = target.get_pixel(x, y);
+    let mut result = 0;
+    for shift in [0, 8, 16] {
+        let s = color >> shift & 0xFF;
+        let d = dst >> shift & 0xFF;
+        result |= ((s * alpha + d * (255 - alpha)) / 255) << shift;
+    }
+    target.set_pixel(x, y, result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, color: u32) -> RgbaImage {
+        let mut image = RgbaImage::new(width, height);
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                PixelTarget::set_pixel(&mut image, x, y, color);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn glyph_pixels() {
+        let mut target = image(10, 10, 0x000000);
+        let mut overlay = Overlay::new();
+        overlay.text(1, 1, "T", WHITE);
+        overlay.draw(&mut target);
+        // 'T': верхняя строка 0x3F - шесть пикселей, вторая 0x2D
+        for (gy, bits) in [0x3F, 0x2D].into_iter().enumerate() {
+            for gx in 0..GLYPH_WIDTH {
+                let expected = if bits >> gx & 1 == 1 { 0xFFFFFF } else { 0x000000 };
+                assert_eq!(PixelTarget::get_pixel(&target, 1 + gx, 1 + gy), expected, "({gx}, {gy})");
+            }
+        }
+        // слева и сверху от глифа ничего не задето
+        assert!((0..10).all(|i| PixelTarget::get_pixel(&target, i, 0) == 0 && PixelTarget::get_pixel(&target, 0, i) == 0));
+        assert!(target.pixels().all(|p| p.0[3] == 255));
+    }
+
+    #[test]
+    fn alpha_blending() {
+        let mut target = image(4, 4, 0xFFFFFF);
+        let mut overlay = Overlay::new();
+        overlay.fill_rect(0, 0, 2, 4, SHADOW);
+        overlay.fill_rect(2, 0, 2, 4, 0x00FF_0000);
+        overlay.line(0, 3, 3, 0, RED);
+        overlay.draw(&mut target);
+        // половина чёрного поверх белого, полностью прозрачный цвет ничего не меняет
+        assert_eq!(PixelTarget::get_pixel(&target, 0, 0), 0x7F7F7F);
+        assert_eq!(PixelTarget::get_pixel(&target, 3, 3), 0xFFFFFF);
+        for i in 0..4 {
+            assert_eq!(PixelTarget::get_pixel(&target, i, 3 - i), 0xFF0000);
+        }
+    }
+
+    #[test]
+    fn shapes_are_clipped() {
+        let mut target = image(4, 4, 0x000000);
+        let mut overlay = Overlay::new();
+        overlay.rect(-2, -2, 4, 4, GREEN);
+        overlay.line(-10, 2, 10, 2, BLUE);
+        overlay.text(3, 3, "W", YELLOW);
+        overlay.draw(&mut target);
+        assert_eq!(PixelTarget::get_pixel(&target, 1, 1), 0x00FF00);
+        assert_eq!(PixelTarget::get_pixel(&target, 0, 0), 0x000000);
+        assert!((0..4).all(|x| PixelTarget::get_pixel(&target, x, 2) == 0x0000FF));
+        assert!(!overlay.is_empty());
+        overlay.clear();
+        assert!(overlay.shapes().is_empty());
+    }
+}
@@ -0,0 +1,479 @@
+// Постобработка готового кадра. Цвета - Vector<3, f32> с компонентами 0..1,
+// пиксель (x, y) лежит по индексу x + y * width, глубина - расстояние по оси взгляда
+use image::RgbaImage;
+
+use crate::geometry::Vector;
+
+type Color = Vector<3, f32>;
+
+/// Кадр в виде, удобном для проходов постобработки
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image { width, height, pixels: vec![Vector::empty(); width * height] }
+    }
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[x + y * self.width]
+    }
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[x + y * self.width] = color;
+    }
+    // с прижатием координат к краю
+    fn at(&self, x: isize, y: isize) -> Color {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.get(x, y)
+    }
+    fn luma_at(&self, x: isize, y: isize) -> f32 {
+        luma(self.at(x, y))
+    }
+}
+
+pub fn luma(c: Color) -> f32 {
+    c * Vector::new([0.299, 0.587, 0.114])
+}
+
+pub fn from_rgb(color: u32) -> Color {
+    Vector::new([(color >> 16 & 0xFF) as f32, (color >> 8 & 0xFF) as f32, (color & 0xFF) as f32]) / 255.0
+}
+
+pub fn to_rgb(color: Color) -> u32 {
+    let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u32;
+    channel(color.x()) << 16 | channel(color.y()) << 8 | channel(color.z())
+}
+
+/// Трёхмерная таблица цветокоррекции size x size x size
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut {
+    size: usize,
+    // индекс r + g * size + b * size * size
+    table: Vec<Color>,
+}
+
+impl Lut {
+    pub fn from_fn(size: usize, f: impl Fn(Color) -> Color) -> Lut {
+        let size = size.max(2);
+        let step = 1.0 / (size - 1) as f32;
+        let mut table = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    table.push(f(Vector::new([r as f32, g as f32, b as f32]) * step));
+                }
+            }
+        }
+        Lut { size, table }
+    }
+    pub fn identity(size: usize) -> Lut {
+        Lut::from_fn(size, |c| c)
+    }
+    /// Таблица из картинки-полосы шириной size * size и высотой size: срезы по синему идут
+    /// слева направо, внутри среза красный растёт вправо, зелёный - вниз. None, если размеры не такие
+    pub fn from_image(image: &RgbaImage) -> Option<Lut> {
+        let size = image.height() as usize;
+        if size < 2 || image.width() as usize != size * size {
+            return None;
+        }
+        let mut table = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let [pr, pg, pb, _] = image.get_pixel((b * size + r) as u32, g as u32).0;
+                    table.push(Vector::new([pr as f32, pg as f32, pb as f32]) / 255.0);
+                }
+            }
+        }
+        Some(Lut { size, table })
+    }
+    fn entry(&self, r: usize, g: usize, b: usize) -> Color {
+        self.table[r + g * self.size + b * self.size * self.size]
+    }
+    /// Трилинейная интерполяция между узлами таблицы
+    pub fn sample(&self, color: Color) -> Color {
+        let scale = (self.size - 1) as f32;
+        let p = color.clamp(Vector::empty(), Vector::new([1.0, 1.0, 1.0])) * scale;
+        let i = [0, 1, 2].map(|axis| (p[axis].floor() as usize).min(self.size - 2));
+        let t = [0, 1, 2].map(|axis| p[axis] - i[axis] as f32);
+        let mut result: Color = Vector::empty();
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = i;
+            for axis in 0..3 {
+                if corner & (1 << axis) != 0 {
+                    index[axis] += 1;
+                    weight *= t[axis];
+                } else {
+                    weight *= 1.0 - t[axis];
+                }
+            }
+            result = result + self.entry(index[0], index[1], index[2]) * weight;
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pass {
+    /// Сглаживание краёв по яркости (FXAA 3.11, качество "console")
+    Fxaa,
+    /// Затемнение к углам: strength - сила у углов, radius - доля полудиагонали, где оно начинается
+    Vignette { strength: f32, radius: f32 },
+    /// Свечение ярких мест: яркость выше threshold размывается на radius пикселей и добавляется с весом intensity
+    Bloom { threshold: f32, intensity: f32, radius: usize },
+    ColorGrade(Lut),
+    /// Повышение резкости вычитанием размытого соседями
+    Sharpen { amount: f32 },
+    /// Размытие вне фокуса: резко на расстоянии focus, полностью размыто дальше range от него
+    DepthOfField { focus: f32, range: f32, radius: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostPass {
+    pub pass: Pass,
+    pub enabled: bool,
+}
+
+/// Цепочка проходов, применяются по порядку
+#[derive(Debug, Clone, Default)]
+pub struct PostProcess {
+    pub passes: Vec<PostPass>,
+}
+
+impl PostProcess {
+    pub fn new() -> PostProcess {
+        PostProcess::default()
+    }
+    pub fn push(&mut self, pass: Pass) -> &mut Self {
+        self.passes.push(PostPass { pass, enabled: true });
+        self
+    }
+    pub fn is_active(&self) -> bool {
+        self.passes.iter().any(|p| p.enabled)
+    }
+    /// Применяет включённые проходы; depth нужна только для DepthOfField
+    pub fn apply(&self, image: &mut Image, depth: &[f32]) {
+        for PostPass { pass, enabled } in &self.passes {
+            if !enabled {
+                continue;
+            }
+            match pass {
+                Pass::Fxaa => *image = fxaa(image),
+                Pass::Vignette { strength, radius } => vignette(image, *strength, *radius),
+                Pass::Bloom { threshold, intensity, radius } => bloom(image, *threshold, *intensity, *radius),
+                Pass::ColorGrade(lut) => image.pixels.iter_mut().for_each(|c| *c = lut.sample(*c)),
+                Pass::Sharpen { amount } => *image = sharpen(image, *amount),
+                Pass::DepthOfField { focus, range, radius } => depth_of_field(image, depth, *focus, *range, *radius),
+            }
+        }
+    }
+}
+
+const FXAA_EDGE_THRESHOLD: f32 = 0.125;
+const FXAA_EDGE_THRESHOLD_MIN: f32 = 0.0312;
+const FXAA_SUBPIXEL: f32 = 0.75;
+const FXAA_SEARCH_STEPS: isize = 12;
+
+fn fxaa(image: &Image) -> Image {
+    let mut result = image.clone();
+    for y in 0..image.height as isize {
+        for x in 0..image.width as isize {
+            let m = image.luma_at(x, y);
+            let n = image.luma_at(x, y + 1);
+            let s = image.luma_at(x, y - 1);
+            let e = image.luma_at(x + 1, y);
+            let w = image.luma_at(x - 1, y);
+            let max = m.max(n).max(s).max(e).max(w);
+            let range = max - m.min(n).min(s).min(e).min(w);
+            if range < FXAA_EDGE_THRESHOLD_MIN.max(max * FXAA_EDGE_THRESHOLD) {
+                continue;
+            }
+            let ne = image.luma_at(x + 1, y + 1);
+            let nw = image.luma_at(x - 1, y + 1);
+            let se = image.luma_at(x + 1, y - 1);
+            let sw = image.luma_at(x - 1, y - 1);
+
+            // край горизонтальный, если яркость сильнее меняется по вертикали
+            let change_y = (n + s - 2.0 * m).abs() * 2.0 + (ne + se - 2.0 * e).abs() + (nw + sw - 2.0 * w).abs();
+            let change_x = (e + w - 2.0 * m).abs() * 2.0 + (ne + nw - 2.0 * n).abs() + (se + sw - 2.0 * s).abs();
+            let is_horizontal = change_y >= change_x;
+
+            // шаг поперёк края к более контрастному соседу
+            let (luma1, luma2) = if is_horizontal { (s, n) } else { (w, e) };
+            let (gradient1, gradient2) = ((luma1 - m).abs(), (luma2 - m).abs());
+            let (step, local_average, gradient) = if gradient1 >= gradient2 {
+                (-1, 0.5 * (luma1 + m), gradient1)
+            } else {
+                (1, 0.5 * (luma2 + m), gradient2)
+            };
+            let gradient_scaled = 0.25 * gradient;
+            let along = |i: isize| if is_horizontal { (x + i, y) } else { (x, y + i) };
+            // средняя яркость на границе между этой строкой и соседней, в i пикселях вдоль края
+            let edge_luma = |i: isize| {
+                let (px, py) = along(i);
+                let (qx, qy) = if is_horizontal { (px, py + step) } else { (px + step, py) };
+                0.5 * (image.luma_at(px, py) + image.luma_at(qx, qy)) - local_average
+            };
+            let search = |direction: isize| {
+                let mut i = direction;
+                let mut end = edge_luma(i);
+                while end.abs() < gradient_scaled && i.abs() < FXAA_SEARCH_STEPS {
+                    i += direction;
+                    end = edge_luma(i);
+                }
+                (i.abs() as f32, end)
+            };
+            let (distance1, end1) = search(-1);
+            let (distance2, end2) = search(1);
+            let (distance, end) = if distance1 < distance2 { (distance1, end1) } else { (distance2, end2) };
+            let edge_length = distance1 + distance2;
+            // смещаться к соседу, только если яркость на ближнем конце края меняется в нужную сторону
+            let edge_offset = if (end < 0.0) != (m < local_average) { 0.5 - distance / edge_length } else { 0.0 };
+
+            let neighbours = (2.0 * (n + s + e + w) + ne + nw + se + sw) / 12.0;
+            let subpixel = ((neighbours - m).abs() / range).clamp(0.0, 1.0);
+            let subpixel = (-2.0 * subpixel + 3.0) * subpixel * subpixel;
+            let offset = edge_offset.max(subpixel * subpixel * FXAA_SUBPIXEL);
+
+            let (nx, ny) = if is_horizontal { (x, y + step) } else { (x + step, y) };
+            let color = image.at(x, y).lerp(image.at(nx, ny), offset);
+            result.set(x as usize, y as usize, color);
+        }
+    }
+    result
+}
+
+fn vignette(image: &mut Image, strength: f32, radius: f32) {
+    let center = Vector::new([image.width as f32, image.height as f32]) * 0.5;
+    let half_diagonal = center.norm().max(1.0);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let d = (Vector::new([x as f32 + 0.5, y as f32 + 0.5]) - center).norm() / half_diagonal;
+            let t = ((d - radius) / (1.0 - radius).max(f32::EPSILON)).clamp(0.0, 1.0);
+            let smooth = t * t * (3.0 - 2.0 * t);
+            let c = image.get(x, y);
+            image.set(x, y, c * (1.0 - strength * smooth));
+        }
+    }
+}
+
+// два прохода скользящего среднего по каждой оси - почти гауссово размытие
+fn blur(image: &Image, radius: usize) -> Image {
+    let mut result = image.clone();
+    if radius == 0 {
+        return result;
+    }
+    for _ in 0..2 {
+        result = box_blur(&result, radius, true);
+        result = box_blur(&result, radius, false);
+    }
+    result
+}
+
+fn box_blur(image: &Image, radius: usize, horizontal: bool) -> Image {
+    let mut result = Image::new(image.width, image.height);
+    let (lines, length) = if horizontal { (image.height, image.width) } else { (image.width, image.height) };
+    let r = radius as isize;
+    let scale = 1.0 / (2 * radius + 1) as f32;
+    for line in 0..lines as isize {
+        let at = |i: isize| if horizontal { image.at(i, line) } else { image.at(line, i) };
+        let mut sum: Color = Vector::empty();
+        for i in -r..=r {
+            sum = sum + at(i);
+        }
+        for i in 0..length as isize {
+            let (x, y) = if horizontal { (i, line) } else { (line, i) };
+            result.set(x as usize, y as usize, sum * scale);
+            sum = sum + at(i + r + 1) - at(i - r);
+        }
+    }
+    result
+}
+
+fn bloom(image: &mut Image, threshold: f32, intensity: f32, radius: usize) {
+    let mut bright = image.clone();
+    for c in bright.pixels.iter_mut() {
+        let l = luma(*c);
+        *c = if l > threshold { *c * ((l - threshold) / l) } else { Vector::empty() };
+    }
+    let glow = blur(&bright, radius);
+    for (c, g) in image.pixels.iter_mut().zip(glow.pixels) {
+        *c = *c + g * intensity;
+    }
+}
+
+fn sharpen(image: &Image, amount: f32) -> Image {
+    let mut result = image.clone();
+    for y in 0..image.height as isize {
+        for x in 0..image.width as isize {
+            let c = image.at(x, y);
+            let neighbours = (image.at(x - 1, y) + image.at(x + 1, y) + image.at(x, y - 1) + image.at(x, y + 1)) * 0.25;
+            result.set(x as usize, y as usize, c + (c - neighbours) * amount);
+        }
+    }
+    result
+}
+
+// смешивает кадр с его размытой копией по кружку нерезкости, дешевле честного размытия переменным радиусом
+fn depth_of_field(image: &mut Image, depth: &[f32], focus: f32, range: f32, radius: usize) {
+    let blurred = blur(image, radius);
+    for (i, c) in image.pixels.iter_mut().enumerate() {
+        let coc = ((depth[i] - focus).abs() / range.max(f32::EPSILON)).min(1.0);
+        *c = c.lerp(blurred.pixels[i], coc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    fn assert_color_close(actual: Color, expected: Color, eps: f32) {
+        for i in 0..3 {
+            assert!((actual[i] - expected[i]).abs() <= eps, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn identity_lut_is_no_op() {
+        let mut rng = StdRng::seed_from_u64(49);
+        let lut = Lut::identity(16);
+        for _ in 0..1000 {
+            let color = Vector::new([rng.random(), rng.random(), rng.random()]);
+            assert_color_close(lut.sample(color), color, 1e-5);
+        }
+
+        let mut image = Image::new(16, 16);
+        for pixel in &mut image.pixels {
+            *pixel = from_rgb(rng.random_range(0..0x100_0000));
+        }
+        let expected = image.clone();
+        let mut post = PostProcess::new();
+        post.push(Pass::ColorGrade(lut));
+        post.apply(&mut image, &[]);
+        for (actual, expected) in image.pixels.iter().zip(&expected.pixels) {
+            assert_eq!(to_rgb(*actual), to_rgb(*expected));
+        }
+    }
+
+    #[test]
+    fn from_image() {
+        // полоса тождественной таблицы 4x4x4
+        let size = 4;
+        let strip = RgbaImage::from_fn((size * size) as u32, size as u32, |x, y| {
+            let (b, r, g) = (x as usize / size, x as usize % size, y as usize);
+            let channel = |v: usize| (v * 255 / (size - 1)) as u8;
+            image::Rgba([channel(r), channel(g), channel(b), 255])
+        });
+        assert_eq!(Lut::from_image(&strip), Some(Lut::identity(size)));
+
+        for (width, height) in [(15, 4), (4, 4), (16, 16), (1, 1), (0, 0)] {
+            assert_eq!(Lut::from_image(&RgbaImage::new(width, height)), None, "{width}x{height}");
+        }
+    }
+
+    fn flat(width: usize, height: usize, color: Color) -> Image {
+        let mut image = Image::new(width, height);
+        image.pixels.fill(color);
+        image
+    }
+    fn apply(image: &mut Image, depth: &[f32], pass: Pass) {
+        let mut post = PostProcess::new();
+        post.push(pass);
+        post.apply(image, depth);
+    }
+
+    #[test]
+    fn flat_image_passes_through() {
+        let gray = Vector::new([0.4, 0.4, 0.4]);
+        let mut image = flat(16, 16, gray);
+        let depth: Vec<f32> = (0..16 * 16).map(|i| i as f32).collect();
+        let mut post = PostProcess::new();
+        post.push(Pass::Fxaa)
+            .push(Pass::Bloom { threshold: 0.8, intensity: 1.0, radius: 3 })
+            .push(Pass::Sharpen { amount: 1.0 })
+            .push(Pass::DepthOfField { focus: 0.0, range: 10.0, radius: 3 });
+        post.apply(&mut image, &depth);
+        for c in &image.pixels {
+            assert_color_close(*c, gray, 1e-5);
+        }
+    }
+
+    #[test]
+    fn fxaa_smooths_edges() {
+        // ступенчатая диагональ: выше неё белое, ниже чёрное
+        let mut image = Image::new(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                if 2 * x > y {
+                    image.set(x, y, Vector::new([1.0; 3]));
+                }
+            }
+        }
+        let expected = image.clone();
+        apply(&mut image, &[], Pass::Fxaa);
+        let blended = image.pixels.iter().filter(|c| c.x() > 0.01 && c.x() < 0.99).count();
+        assert!(blended > 8, "only {blended} pixels blended");
+        // вдали от края ничего не меняется
+        assert_eq!(image.get(15, 0), expected.get(15, 0));
+        assert_eq!(image.get(0, 15), expected.get(0, 15));
+    }
+
+    #[test]
+    fn bloom_spreads_only_bright_pixels() {
+        let mut image = Image::new(32, 32);
+        image.set(8, 8, Vector::new([1.0; 3]));
+        image.set(24, 24, Vector::new([0.5; 3]));
+        apply(&mut image, &[], Pass::Bloom { threshold: 0.8, intensity: 1.0, radius: 2 });
+        assert!(image.get(9, 8).x() > 0.0 && image.get(8, 10).x() > 0.0);
+        assert!(image.get(8, 8).x() > 1.0);
+        // тусклый пиксель не светится и сам не меняется (скользящая сумма оставляет только ошибку округления)
+        assert_color_close(image.get(25, 24), Vector::empty(), 1e-6);
+        assert_color_close(image.get(24, 23), Vector::empty(), 1e-6);
+        assert_color_close(image.get(24, 24), Vector::new([0.5; 3]), 1e-6);
+    }
+
+    #[test]
+    fn vignette_darkens_corners() {
+        let white = Vector::new([1.0; 3]);
+        let mut image = flat(16, 16, white);
+        apply(&mut image, &[], Pass::Vignette { strength: 0.5, radius: 0.5 });
+        assert_eq!(image.get(7, 7), white);
+        assert_eq!(image.get(8, 8), white);
+        for (x, y) in [(0, 0), (15, 0), (0, 15), (15, 15)] {
+            assert!(image.get(x, y).x() < 0.6, "{x}, {y}: {:?}", image.get(x, y));
+        }
+    }
+
+    #[test]
+    fn depth_of_field_keeps_focus_sharp() {
+        // шахматная доска: левая половина на расстоянии фокуса, правая далеко за ним
+        let mut image = Image::new(16, 16);
+        let mut depth = vec![0.0; 16 * 16];
+        for y in 0..16 {
+            for x in 0..16 {
+                if (x + y) % 2 == 0 {
+                    image.set(x, y, Vector::new([1.0; 3]));
+                }
+                depth[x + y * 16] = if x < 8 { 5.0 } else { 50.0 };
+            }
+        }
+        let expected = image.clone();
+        apply(&mut image, &depth, Pass::DepthOfField { focus: 5.0, range: 10.0, radius: 2 });
+        for y in 0..16 {
+            for x in 0..16 {
+                if x < 8 {
+                    assert_eq!(image.get(x, y), expected.get(x, y), "{x}, {y}");
+                } else {
+                    assert!(image.get(x, y).x() > 0.2 && image.get(x, y).x() < 0.8, "{x}, {y}: {:?}", image.get(x, y));
+                }
+            }
+        }
+    }
+}
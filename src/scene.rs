@@ -1,65 +1,364 @@
 
-use sdl2::{Sdl, event::Event, keyboard::Keycode, pixels::{Color, PixelFormatEnum}, rect::Rect, render::{Canvas, TextureCreator}, video::{Window, WindowContext} };
-use std::mem;
-use crate::{Shader, geometry::{Matrix, Vector}, model::Model};
+use sdl2::{Sdl, event::{Event, WindowEvent}, keyboard::Keycode, pixels::{Color, PixelFormatEnum}, render::{Canvas, Texture, TextureCreator}, video::{Window, WindowContext} };
+use std::{mem, thread, time::{Duration, Instant}};
+use image::{Rgba, RgbaImage};
+use crate::{Shader, deferred::{self, GBuffer, Light, Material, NO_MATERIAL}, geometry::{Matrix, Vector}, input::InputState, overlay::{Overlay, PixelTarget}, postprocess::{self, PostProcess}};
+
+/// Верхняя граница времени кадра, секунды: после долгого кадра (перетаскивание окна, загрузка)
+/// камера не улетает, а фиксированный шаг не догоняет время сотнями update
+const MAX_FRAME_TIME: f32 = 0.1;
+/// Пауза цикла, когда перерисовывать нечего
+const IDLE_FRAME_TIME: f32 = 0.01;
+
+/// Как Scene::triangle рисует треугольники
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PolygonMode {
+    #[default]
+    Fill,
+    /// Только рёбра
+    Line,
+    /// Заливка и рёбра поверх неё
+    FillAndLine,
+}
+
+/// Как закрашиваются непрозрачные треугольники
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderPath {
+    /// Шейдер и освещение в каждом фрагменте, прошедшем тест глубины
+    #[default]
+    Forward,
+    /// Фрагменты пишутся в G-буфер, освещение считается в Scene::light_pass один раз на пиксель.
+    /// MSAA в этом режиме выключен: в G-буфере одна выборка на пиксель
+    Deferred,
+}
+
+/// Положения выборок MSAA внутри пикселя относительно его центра, в 1/16 пикселя,
+/// стандартные шаблоны D3D для 2x, 4x и 8x
+const MSAA_2X: [(i8, i8); 2] = [(4, 4), (-4, -4)];
+const MSAA_4X: [(i8, i8); 4] = [(-2, -6), (6, -2), (-6, 2), (2, 6)];
+const MSAA_8X: [(i8, i8); 8] = [(1, -3), (-1, 3), (5, 1), (-3, -5), (-5, 5), (-7, -1), (3, 7), (7, -7)];
+
+/// Что показывает буфер цвета вместо обычного изображения
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ViewMode {
+    #[default]
+    Color,
+    /// Линейная глубина между ближайшим и дальним фрагментом кадра, ближе - светлее
+    Depth,
+    /// Нормали в пространстве модели, компоненты [-1, 1] -> [0, 255]
+    WorldNormals,
+    /// Нормали в пространстве камеры
+    ViewNormals,
+    /// u - красный, v - зелёный
+    Uv,
+    Barycentric,
+    /// Свой цвет у каждого треугольника кадра
+    TriangleId,
+    /// Сколько раз в пиксель записывались фрагменты: синий - 1, красный - 5 и больше
+    Overdraw,
+}
+
+impl ViewMode {
+    pub const ALL: [ViewMode; 8] = [
+        ViewMode::Color, ViewMode::Depth, ViewMode::WorldNormals, ViewMode::ViewNormals,
+        ViewMode::Uv, ViewMode::Barycentric, ViewMode::TriangleId, ViewMode::Overdraw,
+    ];
+    /// Следующий режим по кругу
+    pub fn next(self) -> ViewMode {
+        let index = ViewMode::ALL.iter().position(|&mode| mode == self).unwrap();
+        ViewMode::ALL[(index + 1) % ViewMode::ALL.len()]
+    }
+}
+
+/// Настройки главного цикла Scene::run
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoopSettings {
+    /// Шаг update в секундах, больше нуля; None - один update на кадр с шагом, равным времени кадра
+    pub fixed_step: Option<f32>,
+    /// Ограничение частоты кадров, больше нуля; None - без ограничения (кроме vsync)
+    pub max_fps: Option<f32>,
+    /// Перерисовывать только если update вернул true или окно требует перерисовки
+    pub redraw_on_change: bool,
+}
+
+/// Время, передаваемое в update и render
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameTime {
+    /// В update - шаг, в render - время с прошлого отрисованного кадра, секунды
+    pub dt: f32,
+    /// Время с начала цикла, секунды
+    pub total: f64,
+    /// Число отрисованных кадров
+    pub frame: u64,
+    /// Доля фиксированного шага, накопленная после последнего update, для интерполяции в render
+    pub alpha: f32,
+}
+
+/// Счётчики за кадр, Scene::run сбрасывает их перед каждым render
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    pub models_tested: usize,
+    pub models_culled: usize,
+    pub chunks_tested: usize,
+    pub chunks_culled: usize,
+    pub triangles: usize,
+}
+
+impl FrameStats {
+    /// Учитывает результат отсечения модели и возвращает visible
+    pub fn cull_model(&mut self, visible: bool) -> bool {
+        self.models_tested += 1;
+        if !visible {
+            self.models_culled += 1;
+        }
+        visible
+    }
+    /// Учитывает результат отсечения куска модели и возвращает visible
+    pub fn cull_chunk(&mut self, visible: bool) -> bool {
+        self.chunks_tested += 1;
+        if !visible {
+            self.chunks_culled += 1;
+        }
+        visible
+    }
+}
+
 pub struct Scene {
     scene: Vec<Vec<u32>>,
     zbuffer: Vec<f32>,
     canvas: Canvas<Window>,
-    texture_creator: TextureCreator<WindowContext>,
     sdl_context: Sdl,
     width: usize,
     height: usize,
+    /// Отношение размера буферов к размеру окна в пикселях
+    resolution_scale: f32,
+    pub stats: FrameStats,
+    /// Двумерный слой, в run рисуется поверх кадра после render и затем очищается
+    pub overlay: Overlay,
+    pub polygon_mode: PolygonMode,
+    /// Цвет рёбер в режимах Line и FillAndLine
+    pub wireframe_color: u32,
+    /// Насколько линия может быть дальше записанной глубины и всё равно пройти тест,
+    /// чтобы рёбра не тонули в собственной заливке
+    pub line_depth_bias: f32,
+    pub view_mode: ViewMode,
+    /// Постобработка, в run применяется к кадру после render и до оверлея
+    pub post: PostProcess,
+    /// near и far проекции, по ним ViewMode::Depth и постобработка восстанавливают линейную глубину
+    pub clip_planes: (f32, f32),
+    /// Глубина из viewport: буфер глубины хранит значения от 0 до depth
+    depth: f32,
+    /// Число записей фрагментов в каждый пиксель за кадр
+    overdraw: Vec<u32>,
+    /// Шаблон выборок MSAA, пустой - без MSAA
+    msaa_pattern: &'static [(i8, i8)],
+    /// Цвет и глубина выборок, выборки пикселя (x, y) начинаются с (x + y * width) * число выборок
+    sample_color: Vec<u32>,
+    sample_depth: Vec<f32>,
+    /// Во сколько раз по каждой оси буферы больше выводимого кадра
+    ssaa: usize,
+    pub render_path: RenderPath,
+    /// Источники света в пространстве камеры; пустой список - цвет шейдера без освещения
+    pub lights: Vec<Light>,
+    /// Материалы по номерам из Shader::material, для отсутствующих - Material::default()
+    pub materials: Vec<Material>,
+    /// Обратная к viewport * projection: по ней освещение восстанавливает положение пикселя из глубины
+    pub unproject: Matrix<4, 4>,
+    /// Непрозрачность треугольников: меньше 1 - смешиваются с кадром и не пишут глубину.
+    /// В отложенном режиме первый такой треугольник сначала освещает G-буфер
+    pub alpha: f32,
+    gbuffer: GBuffer,
+    /// G-буфер этого кадра уже освещён, треугольники идут прямым путём
+    lit: bool,
 }
 
 impl Scene {
     pub fn new(width: usize, height: usize, depth: usize) -> Scene {
+        Scene::with_vsync(width, height, depth, false)
+    }
+    /// С vsync present ждёт обратного хода луча, и частота кадров не выше частоты монитора
+    pub fn with_vsync(width: usize, height: usize, depth: usize, vsync: bool) -> Scene {
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
 
         let window = video_subsystem.window("demo", width as u32, height as u32)
             .position_centered()
+            .resizable()
+            .allow_highdpi()
             .build()
             .unwrap();
 
-        let mut canvas = window.into_canvas().build().unwrap();
+        let mut canvas_builder = window.into_canvas();
+        if vsync {
+            canvas_builder = canvas_builder.present_vsync();
+        }
+        let mut canvas = canvas_builder.build().unwrap();
 
-        let  texture_creator = canvas.texture_creator();
 
         
 
         canvas.set_draw_color(Color::RGB(0, 0, 12));
         canvas.clear();
 
-        Scene {
-            scene: vec![vec![0; width]; height],
-            zbuffer: vec![f32::MIN; width*height],
+        let mut scene = Scene {
+            scene: vec![],
+            zbuffer: vec![],
             canvas,
-            texture_creator,
             sdl_context,
-            width,
-            height,
+            width: 0,
+            height: 0,
+            resolution_scale: 1.0,
+            stats: FrameStats::default(),
+            overlay: Overlay::new(),
+            polygon_mode: PolygonMode::Fill,
+            wireframe_color: 0xFFFFFF,
+            line_depth_bias: depth as f32 * 1e-4,
+            view_mode: ViewMode::Color,
+            post: PostProcess::new(),
+            clip_planes: (0.1, 100.0),
+            depth: depth as f32,
+            overdraw: vec![],
+            msaa_pattern: &[],
+            sample_color: vec![],
+            sample_depth: vec![],
+            ssaa: 1,
+            render_path: RenderPath::Forward,
+            lights: vec![],
+            materials: vec![],
+            unproject: Matrix::identity(),
+            alpha: 1.0,
+            gbuffer: GBuffer::default(),
+            lit: false,
+        };
+        // на high-DPI экранах пикселей больше, чем единиц размера окна
+        scene.fit_to_window();
+        scene
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn resolution_scale(&self) -> f32 {
+        self.resolution_scale
+    }
+    /// Рисовать в буферы размером scale от окна, при выводе они растягиваются на всё окно
+    pub fn set_resolution_scale(&mut self, scale: f32) {
+        self.resolution_scale = scale.max(0.01);
+        self.fit_to_window();
+    }
+    /// Пересоздаёт буферы под текущий размер окна, возвращает true, если размер изменился
+    pub fn fit_to_window(&mut self) -> bool {
+        let (w, h) = self.canvas.output_size().unwrap();
+        let width = ((w as f32 * self.resolution_scale) as usize).max(1) * self.ssaa;
+        let height = ((h as f32 * self.resolution_scale) as usize).max(1) * self.ssaa;
+        if (width, height) == (self.width, self.height) {
+            return false;
         }
+        self.resize(width, height);
+        true
     }
-    pub fn update(&mut self) {
-
-        let mut scene_render = self.texture_creator.create_texture_streaming(PixelFormatEnum::RGB24, 
-                                       self.width as u32, self.height as u32).unwrap();
-        scene_render.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+    /// Меняет размер буферов цвета и глубины, содержимое очищается
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.scene = vec![vec![0; height]; width];
+        self.zbuffer = vec![f32::MAX; width*height];
+        self.overdraw = vec![0; width*height];
+        let samples = self.msaa_pattern.len();
+        self.sample_color = vec![0; width*height*samples];
+        self.sample_depth = vec![f32::MAX; width*height*samples];
+        self.gbuffer = GBuffer::new(width, height);
+    }
+    /// MSAA с samples выборками на пиксель: 1 (выключено), 2, 4 или 8, другие числа округляются
+    /// вниз до ближайшего из них. Треугольник закрашивается один раз на пиксель, а цвет и глубина
+    /// хранятся для каждой выборки и усредняются в resolve
+    pub fn set_msaa(&mut self, samples: usize) {
+        let pattern: &'static [(i8, i8)] = match samples {
+            0 | 1 => &[],
+            2 | 3 => &MSAA_2X,
+            4..=7 => &MSAA_4X,
+            _ => &MSAA_8X,
+        };
+        if pattern.len() != self.msaa_pattern.len() {
+            self.msaa_pattern = pattern;
+            self.resize(self.width, self.height);
+        }
+    }
+    pub fn msaa(&self) -> usize {
+        self.samples().max(1)
+    }
+    // выборок MSAA на пиксель, 0 - без MSAA
+    fn samples(&self) -> usize {
+        match self.render_path {
+            RenderPath::Forward => self.msaa_pattern.len(),
+            RenderPath::Deferred => 0,
+        }
+    }
+    /// Суперсэмплинг: буферы в factor раз больше кадра по каждой оси, при выводе
+    /// усредняются блоки factor x factor. Оверлей рисуется в буферы, так что текст мельче
+    pub fn set_ssaa(&mut self, factor: usize) {
+        self.ssaa = factor.max(1);
+        self.fit_to_window();
+    }
+    pub fn ssaa(&self) -> usize {
+        self.ssaa
+    }
+    /// Размер выводимого кадра: буферы без суперсэмплинга
+    pub fn output_size(&self) -> (usize, usize) {
+        (self.width / self.ssaa, self.height / self.ssaa)
+    }
+    /// Усредняет выборки MSAA в буфер цвета, в буфер глубины попадает ближайшая из них.
+    /// Scene::run вызывает его после render
+    pub fn resolve(&mut self) {
+        let samples = self.samples();
+        if samples == 0 {
+            return;
+        }
+        for x in 0..self.width {
             for y in 0..self.height {
-                for x in 0..self.width {
+                let first = (x + y * self.width) * samples;
+                self.scene[x][y] = average(self.sample_color[first..first + samples].iter().copied());
+                self.zbuffer[x + y * self.width] = self.sample_depth[first..first + samples].iter().copied().fold(f32::MAX, f32::min);
+            }
+        }
+    }
+    // пиксель выводимого кадра, сверху вниз; при суперсэмплинге - среднее блока
+    fn output_pixel(&self, x: usize, y: usize) -> u32 {
+        let n = self.ssaa;
+        if n == 1 {
+            return self.scene[x][self.height - y - 1];
+        }
+        average((0..n * n).map(|i| self.scene[x * n + i % n][self.height - y * n - i / n - 1]))
+    }
+    /// Матрица viewport c полями margin (доля размера) с каждой стороны под текущий размер буферов
+    pub fn viewport(&self, margin: f32, depth: f32) -> Matrix<4, 4> {
+        let (w, h) = (self.width as f32, self.height as f32);
+        Matrix::viewport(w * margin, h * margin, w * (1.0 - 2.0 * margin), h * (1.0 - 2.0 * margin), depth)
+    }
+    fn create_texture<'t>(&self, texture_creator: &'t TextureCreator<WindowContext>) -> Texture<'t> {
+        let (width, height) = self.output_size();
+        texture_creator.create_texture_streaming(PixelFormatEnum::RGB24,
+                                       width as u32, height as u32).unwrap()
+    }
+    fn present(&mut self, scene_render: &mut Texture) {
+        let (width, height) = self.output_size();
+        scene_render.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            for y in 0..height {
+                for x in 0..width {
                     let offset = y*pitch + x*3;
-                    let color = self.scene[x][self.height - y - 1];
-                    buffer[offset + 0] = (color >> (8*2)) as u8;
-                    buffer[offset + 1] = (color >> (8*1)) as u8;
+                    let color = self.output_pixel(x, y);
+                    buffer[offset] = (color >> 16) as u8;
+                    buffer[offset + 1] = (color >> 8) as u8;
                     buffer[offset + 2] = color as u8;
                 }
             }
         }).unwrap();
 
         self.canvas.clear();
-        self.canvas.copy(&scene_render, None, Some(Rect::new(0, 0, 
-                                                self.width as u32, self.height as u32))).unwrap();
+        // растягивается на всё окно, если буферы отличаются от него размером
+        self.canvas.copy(scene_render, None, None).unwrap();
         self.canvas.present();
 
     }
@@ -67,14 +366,130 @@ impl Scene {
     pub fn set(&mut self, x: usize, y: usize, color: u32) {
         self.scene[x][y] = color;
     }
+    /// Рисует overlay в буфер цвета и очищает его
+    pub fn draw_overlay(&mut self) {
+        let overlay = mem::take(&mut self.overlay);
+        overlay.draw(self);
+        self.overlay = overlay;
+        self.overlay.clear();
+    }
+    /// Выводимый кадр в виде картинки (верхняя строка - верх кадра), например для сохранения кадра
+    pub fn to_image(&self) -> RgbaImage {
+        let (width, height) = self.output_size();
+        RgbaImage::from_fn(width as u32, height as u32, |x, y| {
+            let color = self.output_pixel(x as usize, y as usize);
+            Rgba([(color >> 16) as u8, (color >> 8) as u8, color as u8, 255])
+        })
+    }
+    /// Треугольник с вершинами в столбцах clipc (пространство отсечения), по polygon_mode
     pub fn triangle(&mut self, clipc: &Matrix<4, 3>, shader: &Shader, viewport: &Matrix<4, 4>) {
-        let pts: Matrix<3, 4> = (viewport.clone() * clipc.clone()).trunspose();
-        let mut pts2: Matrix<3, 2> = Matrix::new();
+        self.stats.triangles += 1;
+        if self.polygon_mode != PolygonMode::Line {
+            self.fill_triangle(clipc, shader, viewport);
+        }
+        if self.polygon_mode != PolygonMode::Fill {
+            for i in 0..3 {
+                self.line(clipc.col(i), clipc.col((i + 1) % 3), viewport, self.wireframe_color, 1.0);
+            }
+        }
+    }
+    /// Отрезок между точками в пространстве отсечения с тестом глубины, толщиной thickness пикселей
+    pub fn line(&mut self, a: Vector<4, f32>, b: Vector<4, f32>, viewport: &Matrix<4, 4>, color: u32, thickness: f32) {
+        let Some((a, b)) = clip_segment(a, b) else {
+            return;
+        };
+        let a = viewport * a;
+        let b = viewport * b;
+        let a = a.xyz() / a.w();
+        let b = b.xyz() / b.w();
+        // Брезенхэм, как в overlay; глубина после деления на w линейна вдоль отрезка на экране
+        let (mut x0, mut y0) = (a.x().round() as i32, a.y().round() as i32);
+        let (x1, y1) = (b.x().round() as i32, b.y().round() as i32);
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let steps = dx.max(-dy).max(1) as f32;
+        let mut error = dx + dy;
+        let mut step = 0;
+        loop {
+            let depth = a.z() + (b.z() - a.z()) * (step as f32 / steps);
+            self.splat(x0 as f32, y0 as f32, depth, color, thickness);
+            if x0 == x1 && y0 == y1 {
+                break;
+            }
+            let e2 = 2 * error;
+            if e2 >= dy {
+                error += dy;
+                x0 += sx;
+            }
+            if e2 <= dx {
+                error += dx;
+                y0 += sy;
+            }
+            step += 1;
+        }
+    }
+    /// Квадратная точка размером size пикселей с тестом глубины
+    pub fn point(&mut self, p: Vector<4, f32>, viewport: &Matrix<4, 4>, color: u32, size: f32) {
+        if (0..3).any(|i| p[i] < -p.w() || p[i] > p.w()) {
+            return;
+        }
+        let p = viewport * p;
+        let p = p.xyz() / p.w();
+        self.splat(p.x(), p.y(), p.z(), color, size);
+    }
+    // квадрат size x size с центром в (x, y), экранные координаты
+    fn splat(&mut self, x: f32, y: f32, depth: f32, color: u32, size: f32) {
+        if depth < 0.0 {
+            return;
+        }
+        let size = size.round().max(1.0) as i32;
+        let x0 = (x - (size - 1) as f32 / 2.0).round() as i32;
+        let y0 = (y - (size - 1) as f32 / 2.0).round() as i32;
+        let samples = self.samples();
+        for px in x0..x0 + size {
+            for py in y0..y0 + size {
+                if px < 0 || py < 0 || px as usize >= self.width || py as usize >= self.height {
+                    continue;
+                }
+                let (px, py) = (px as usize, py as usize);
+                if samples > 0 {
+                    // линия покрывает пиксель целиком, проверяются только глубины выборок
+                    let first = (px + py * self.width) * samples;
+                    for i in first..first + samples {
+                        if depth <= self.sample_depth[i] + self.line_depth_bias {
+                            self.sample_depth[i] = self.sample_depth[i].min(depth);
+                            self.sample_color[i] = color;
+                        }
+                    }
+                    continue;
+                }
+                let z = &mut self.zbuffer[px + py * self.width];
+                if depth > *z + self.line_depth_bias {
+                    continue;
+                }
+                *z = z.min(depth);
+                self.scene[px][py] = color;
+                // освещение не должно затереть линию
+                self.gbuffer.material[px + py * self.width] = NO_MATERIAL;
+            }
+        }
+    }
+    fn fill_triangle(&mut self, clipc: &Matrix<4, 3>, shader: &Shader, viewport: &Matrix<4, 4>) {
+        // прозрачное смешивается с уже освещённым кадром, иначе light_pass потом его затрёт
+        if self.alpha < 1.0 {
+            self.light_pass();
+        }
+        let pts: Matrix<3, 4> = (viewport * clipc).transpose();
+        let mut tri = ScreenTriangle { pts2: Matrix::new(), depth: Vector::empty(), w: Vector::empty() };
 
         for i in 0..3 {
-            pts2[i] = (pts[i] / pts[i][3]).proj::<2>();
-        }                
-        println!("{:?}", clipc.clone());
+            tri.pts2[i] = pts[i].xy() / pts[i].w();
+            tri.depth[i] = pts[i].z() / pts[i].w();
+            tri.w[i] = pts[i].w();
+        }
+        let (pts2, depth, w) = (&tri.pts2, tri.depth, tri.w);
 
         let mut bboxmin = Vector::new([f32::MAX, f32::MAX]);
         let mut bboxmax = Vector::new([-f32::MAX, -f32::MAX]);
@@ -87,40 +502,247 @@ impl Scene {
             }
         }
 
-        let mut p = Vector::new([bboxmin[0], bboxmin[1]]);
         let mut color = 0;
+        let samples = self.samples();
 
-
-        while p[0] <= bboxmax[0] {
-            while p[1] <= bboxmax[1] {
+        for x in bboxmin[0].floor() as usize..=bboxmax[0].ceil() as usize {
+            for y in bboxmin[1].floor() as usize..=bboxmax[1].ceil() as usize {
+                if x >= self.width || y >= self.height {
+                    continue;
+                }
+                if samples > 0 {
+                    self.fill_pixel_msaa(x, y, &tri, shader, &mut color);
+                    continue;
+                }
+                let p = Vector::new([x as f32, y as f32]);
                 let bc_screen = barycentric(pts2[0], pts2[1], pts2[2], p);
-                let mut bc_clip = Vector::new([bc_screen[0]/pts[0][3], bc_screen[1]/pts[1][3], bc_screen[2]/pts[2][3]]);
-                bc_clip = bc_clip/(bc_clip[1] + bc_clip[1] + bc_clip[0]);
-                let frag_depth = clipc[2] * bc_clip;
-
-                if (bc_screen[0] < 0.0 || 
-                        bc_screen[1] < 0.0 || 
-                            bc_screen[2] < 0.0 || 
-                                self.zbuffer[(p[0] + p[1] * self.width as f32) as usize] > frag_depth) {
-                        continue;
-                    }
-                let discard = shader.fragment(bc_clip, &mut color);
-                if !discard {
-                    self.zbuffer[(p[0] + p[1] * self.width as f32) as usize] = frag_depth;
-                    self.set(p[0] as usize, p[1] as usize, color);
+                if bc_screen.x() < 0.0 || bc_screen.y() < 0.0 || bc_screen.z() < 0.0 {
+                    continue;
                 }
 
-                p[1] += 1.0;
+                let mut bc_clip = bc_screen.component_div(w);
+                bc_clip = bc_clip/(bc_clip.x() + bc_clip.y() + bc_clip.z());
+                // экранная глубина линейна в пространстве экрана; меньше — ближе
+                let frag_depth = depth * bc_screen;
+
+                if frag_depth < 0.0 || self.zbuffer[x + y * self.width] <= frag_depth {
+                    continue;
+                }
+                if shader.fragment(bc_clip, &mut color) {
+                    continue;
+                }
+                let index = x + y * self.width;
+                self.overdraw[index] += 1;
+                if let Some(color) = self.view_mode_color(shader, bc_clip) {
+                    self.zbuffer[index] = frag_depth;
+                    self.set(x, y, color);
+                    self.gbuffer.material[index] = NO_MATERIAL;
+                } else if self.render_path == RenderPath::Deferred && !self.lit {
+                    self.zbuffer[index] = frag_depth;
+                    self.gbuffer.set(index, color, shader.view_normal(bc_clip), shader.material);
+                } else {
+                    let lit = self.lit_color(p, frag_depth, color, shader, bc_clip);
+                    if self.alpha < 1.0 {
+                        self.scene[x][y] = blend(self.scene[x][y], lit, self.alpha);
+                    } else {
+                        self.zbuffer[index] = frag_depth;
+                        self.set(x, y, lit);
+                        self.gbuffer.material[index] = NO_MATERIAL;
+                    }
+                }
+            }
+        }
+    }
+    // покрытие и глубина проверяются в каждой выборке, шейдер вызывается один раз
+    // в первой покрытой выборке (ближе к центроиду, чем центр пикселя, который может быть вне треугольника)
+    fn fill_pixel_msaa(&mut self, x: usize, y: usize, tri: &ScreenTriangle, shader: &Shader, color: &mut u32) {
+        let (pts2, depth, w) = (&tri.pts2, tri.depth, tri.w);
+        let samples = self.msaa_pattern.len();
+        let first = (x + y * self.width) * samples;
+        let mut covered = [None; 8];
+        let mut shading_point = None;
+        for (i, &(dx, dy)) in self.msaa_pattern.iter().enumerate() {
+            let p = Vector::new([x as f32 + dx as f32 / 16.0, y as f32 + dy as f32 / 16.0]);
+            let bc_screen = barycentric(pts2[0], pts2[1], pts2[2], p);
+            if bc_screen.x() < 0.0 || bc_screen.y() < 0.0 || bc_screen.z() < 0.0 {
+                continue;
+            }
+            let frag_depth = depth * bc_screen;
+            if frag_depth < 0.0 || self.sample_depth[first + i] <= frag_depth {
+                continue;
+            }
+            covered[i] = Some(frag_depth);
+            shading_point.get_or_insert(bc_screen);
+        }
+        let Some(bc_screen) = shading_point else {
+            return;
+        };
+        let mut bc_clip = bc_screen.component_div(w);
+        bc_clip = bc_clip/(bc_clip.x() + bc_clip.y() + bc_clip.z());
+        if shader.fragment(bc_clip, color) {
+            return;
+        }
+        self.overdraw[x + y * self.width] += 1;
+        let color = self.view_mode_color(shader, bc_clip).unwrap_or_else(|| {
+            let p = Vector::new([x as f32, y as f32]);
+            self.lit_color(p, depth * bc_screen, *color, shader, bc_clip)
+        });
+        for (i, frag_depth) in covered.iter().take(samples).enumerate() {
+            if let Some(frag_depth) = frag_depth {
+                if self.alpha < 1.0 {
+                    self.sample_color[first + i] = blend(self.sample_color[first + i], color, self.alpha);
+                    continue;
+                }
+                self.sample_depth[first + i] = *frag_depth;
+                self.sample_color[first + i] = color;
+            }
+        }
+    }
+    // цвет фрагмента прямого пути под lights
+    fn lit_color(&self, p: Vector<2, f32>, depth: f32, albedo: u32, shader: &Shader, bar: Vector<3, f32>) -> u32 {
+        if self.lights.is_empty() {
+            return albedo;
+        }
+        deferred::shade(albedo, shader.view_normal(bar), self.view_position(p.x(), p.y(), depth),
+                        &self.material(shader.material), &self.lights)
+    }
+    fn view_position(&self, x: f32, y: f32, depth: f32) -> Vector<3, f32> {
+        let p = &self.unproject * Vector::new([x, y, depth, 1.0]);
+        p.xyz() / p.w()
+    }
+    fn material(&self, id: u16) -> Material {
+        self.materials.get(id as usize).copied().unwrap_or_default()
+    }
+    /// Освещает G-буфер в буфер цвета. Всё, что рисуется после, идёт прямым путём поверх освещённого
+    /// кадра с тестом по его глубине - так рисуются прозрачные треугольники. Scene::run вызывает её
+    /// после render, если render не вызвал её сам
+    pub fn light_pass(&mut self) {
+        if self.render_path != RenderPath::Deferred || self.lit {
+            return;
+        }
+        self.lit = true;
+        let (width, zbuffer, unproject, scene) = (self.width, &self.zbuffer, &self.unproject, &mut self.scene);
+        let position = |index: usize| {
+            let p = unproject * Vector::new([(index % width) as f32, (index / width) as f32, zbuffer[index], 1.0]);
+            p.xyz() / p.w()
+        };
+        self.gbuffer.light(&self.materials, &self.lights, position, |index, color| scene[index % width][index / width] = color);
+    }
+    // цвет фрагмента в режимах, которые показывают его атрибуты; None - цвет шейдера
+    fn view_mode_color(&self, shader: &Shader, bar: Vector<3, f32>) -> Option<u32> {
+        match self.view_mode {
+            ViewMode::WorldNormals => Some(direction_color(shader.normal(bar))),
+            ViewMode::ViewNormals => Some(direction_color(shader.view_normal(bar))),
+            ViewMode::Uv => {
+                let uv = shader.uv(bar);
+                Some(rgb(uv.x(), uv.y(), 0.0))
+            },
+            ViewMode::Barycentric => Some(rgb(bar.x(), bar.y(), bar.z())),
+            ViewMode::TriangleId => Some(id_color(self.stats.triangles)),
+            _ => None,
+        }
+    }
+    // z из viewport -> z NDC -> расстояние по оси взгляда
+    fn linear_depth(&self, z: f32) -> f32 {
+        let (near, far) = self.clip_planes;
+        let ndc = 2.0 * z / self.depth - 1.0;
+        2.0 * near * far / (far + near - ndc * (far - near))
+    }
+    /// Применяет включённые проходы post к буферу цвета; пиксели без геометрии
+    /// для глубины считаются бесконечно далёкими. Scene::run вызывает её после resolve
+    pub fn post_process(&mut self) {
+        if !self.post.is_active() {
+            return;
+        }
+        let mut image = postprocess::Image::new(self.width, self.height);
+        let mut depth = vec![f32::INFINITY; self.width * self.height];
+        for x in 0..self.width {
+            for y in 0..self.height {
+                image.set(x, y, postprocess::from_rgb(self.scene[x][y]));
+                let z = self.zbuffer[x + y * self.width];
+                if z != f32::MAX {
+                    depth[x + y * self.width] = self.linear_depth(z);
+                }
             }
-            p[0] += 1.0;
         }
+        self.post.apply(&mut image, &depth);
+        for x in 0..self.width {
+            for y in 0..self.height {
+                self.scene[x][y] = postprocess::to_rgb(image.get(x, y));
+            }
+        }
+    }
+    /// Заменяет буфер цвета содержимым буфера глубины или счётчика перерисовок, если этого требует
+    /// view_mode; Scene::run вызывает её после render
+    pub fn apply_view_mode(&mut self) {
+        match self.view_mode {
+            ViewMode::Depth => {
+                let covered = || self.zbuffer.iter().filter(|z| **z != f32::MAX).map(|&z| self.linear_depth(z));
+                let min = covered().fold(f32::MAX, f32::min);
+                let max = covered().fold(f32::MIN, f32::max);
+                let range = (max - min).max(f32::EPSILON);
+                for x in 0..self.width {
+                    for y in 0..self.height {
+                        let z = self.zbuffer[x + y * self.width];
+                        self.scene[x][y] = if z == f32::MAX {
+                            0
+                        } else {
+                            let v = 1.0 - (self.linear_depth(z) - min) / range;
+                            rgb(v, v, v)
+                        };
+                    }
+                }
+            },
+            ViewMode::Overdraw => {
+                for x in 0..self.width {
+                    for y in 0..self.height {
+                        self.scene[x][y] = heat_color(self.overdraw[x + y * self.width]);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+    pub fn clear(&mut self) {
+        for column in self.scene.iter_mut() {
+            column.fill(0);
+        }
+        self.zbuffer.fill(f32::MAX);
+        self.overdraw.fill(0);
+        self.sample_color.fill(0);
+        self.sample_depth.fill(f32::MAX);
+        self.gbuffer.clear();
+        self.lit = false;
     }
-    pub fn wait_for_exit(&mut self, mut action: impl FnMut(&mut Scene, Vec<Keycode>)) {
+    /// Главный цикл с раздельными update и render. update меняет state по вводу и возвращает,
+    /// изменилось ли что-то видимое; с фиксированным шагом он вызывается столько раз, сколько шагов
+    /// накопилось, и нажатия кадра видит только первый из них. render рисует state в очищенные буферы.
+    /// Quit и Escape завершают цикл
+    pub fn run<S>(&mut self, settings: LoopSettings, state: &mut S,
+                  mut update: impl FnMut(&mut S, &InputState, &FrameTime) -> bool,
+                  mut render: impl FnMut(&S, &mut Scene, &FrameTime)) {
+        // при нулевом шаге цикл update не кончится, при нулевом max_fps время кадра бесконечно
+        if let Some(step) = settings.fixed_step {
+            assert!(step > 0.0, "LoopSettings::fixed_step must be positive, got {step}");
+        }
+        if let Some(fps) = settings.max_fps {
+            assert!(fps > 0.0, "LoopSettings::max_fps must be positive, got {fps}");
+        }
         let mut event_pump = self.sdl_context.event_pump().unwrap();
+        // текстура создаётся один раз на весь цикл
+        let texture_creator = self.canvas.texture_creator();
+        let mut scene_render = self.create_texture(&texture_creator);
+        let mut input = InputState::default();
+        let mut time = FrameTime::default();
+        let mut accumulator = 0.0;
+        let mut dirty = true;
+        let start = Instant::now();
+        let mut last_frame = start;
+        // с redraw_on_change кадры рисуются не на каждой итерации, dt и fps в render считаются от прошлой отрисовки
+        let mut last_render = start;
         // let mut rng = rand::rng();
         'running: loop {
-            let mut keys:Vec<Keycode> = vec![];
-            let mut mouse: Vec<Keycode> = vec![];
+            let frame_start = Instant::now();
 
             for event in event_pump.poll_iter() {
                 
@@ -129,32 +751,157 @@ impl Scene {
                     Event::KeyDown { keycode: Some(Keycode::Escape), ..} => {
                         break 'running;
                     },
-                    Event::KeyDown {keycode: Some(key), ..} => {
-                        keys.push(key);
+                    Event::Window { win_event: WindowEvent::Resized(..) | WindowEvent::SizeChanged(..), .. } => {
+                        self.fit_to_window();
+                        dirty = true;
+                        input.handle_event(event);
+                    },
+                    Event::Window { win_event: WindowEvent::Exposed, .. } => {
+                        dirty = true;
+                        input.handle_event(event);
                     },
-                    _ => {}
+                    _ => input.handle_event(event),
                 }
             }
 
-            action(self, keys);
-            self.update();
+            let elapsed = frame_start.duration_since(last_frame).as_secs_f32().min(MAX_FRAME_TIME);
+            last_frame = frame_start;
+            time.total = frame_start.duration_since(start).as_secs_f64();
+
+            match settings.fixed_step {
+                Some(step) => {
+                    accumulator += elapsed;
+                    while accumulator >= step {
+                        time.dt = step;
+                        dirty |= update(state, &input, &time);
+                        input.begin_frame();
+                        accumulator -= step;
+                    }
+                    time.alpha = accumulator / step;
+                },
+                None => {
+                    time.dt = elapsed;
+                    dirty |= update(state, &input, &time);
+                    input.begin_frame();
+                },
+            }
+
+            let mut min_frame_time = settings.max_fps.map_or(0.0, |fps| 1.0 / fps);
+            if dirty || !settings.redraw_on_change {
+                time.dt = frame_start.duration_since(last_render).as_secs_f32();
+                last_render = frame_start;
+                self.stats = FrameStats::default();
+                self.clear();
+                render(state, self, &time);
+                self.light_pass();
+                self.resolve();
+                self.post_process();
+                self.apply_view_mode();
+                self.draw_overlay();
+                // размер мог измениться из-за окна или set_resolution_scale
+                let query = scene_render.query();
+                if (query.width as usize, query.height as usize) != self.output_size() {
+                    scene_render = self.create_texture(&texture_creator);
+                }
+                self.present(&mut scene_render);
+                time.frame += 1;
+                dirty = false;
+            } else {
+                min_frame_time = min_frame_time.max(IDLE_FRAME_TIME);
+            }
+
+            let spent = frame_start.elapsed().as_secs_f32();
+            if spent < min_frame_time {
+                thread::sleep(Duration::from_secs_f32(min_frame_time - spent));
+            }
+        }
+    }
+}
+
+// Отсекает отрезок в пространстве отсечения по шести плоскостям -w <= x, y, z <= w (Лян-Барски)
+fn clip_segment(a: Vector<4, f32>, b: Vector<4, f32>) -> Option<(Vector<4, f32>, Vector<4, f32>)> {
+    let (mut t0, mut t1) = (0.0_f32, 1.0_f32);
+    for axis in 0..3 {
+        for sign in [1.0, -1.0] {
+            // расстояние до плоскости w + sign * p[axis] = 0, внутри > 0
+            let da = a.w() + sign * a[axis];
+            let db = b.w() + sign * b[axis];
+            if da < 0.0 && db < 0.0 {
+                return None;
+            }
+            if da < 0.0 {
+                t0 = t0.max(da / (da - db));
+            } else if db < 0.0 {
+                t1 = t1.min(da / (da - db));
+            }
         }
     }
+    if t0 > t1 {
+        return None;
+    }
+    Some((a.lerp(b, t0), a.lerp(b, t1)))
 }
 
-fn get_color(color: u32, mut intensity: f32) -> u32{
-    intensity = intensity.clamp(0.0, 1.0); 
+// вершины треугольника после viewport и деления на w
+#[derive(Debug, Clone)]
+struct ScreenTriangle {
+    pts2: Matrix<3, 2>,
+    /// экранная глубина вершин
+    depth: Vector<3, f32>,
+    /// w из пространства отсечения, для перспективной коррекции
+    w: Vector<3, f32>,
+}
 
-    let mut result = (color as f32*intensity) as u32;
-    result += (color as f32*intensity) as u32*256;
-    result += (color as f32*intensity) as u32*256*256;
-    result
+// покомпонентное среднее цветов 0xRRGGBB
+fn average(colors: impl IntoIterator<Item = u32>) -> u32 {
+    let mut sum = [0u32; 3];
+    let mut n = 0;
+    for color in colors {
+        n += 1;
+        for (channel, total) in sum.iter_mut().enumerate() {
+            *total += color >> (8 * (2 - channel)) & 0xFF;
+        }
+    }
+    let n = n.max(1);
+    sum.iter().fold(0, |result, total| (result << 8) | ((total + n / 2) / n))
+}
+// src поверх dst с непрозрачностью alpha
+fn blend(dst: u32, src: u32, alpha: f32) -> u32 {
+    let alpha = (alpha.clamp(0.0, 1.0) * 255.0) as u32;
+    [0, 8, 16].iter().fold(0, |result, shift| {
+        let s = src >> shift & 0xFF;
+        let d = dst >> shift & 0xFF;
+        result | ((s * alpha + d * (255 - alpha)) / 255) << shift
+    })
+}
+fn rgb(r: f32, g: f32, b: f32) -> u32 {
+    let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0) as u32;
+    channel(r) << 16 | channel(g) << 8 | channel(b)
+}
+fn direction_color(n: Vector<3, f32>) -> u32 {
+    rgb(n.x() * 0.5 + 0.5, n.y() * 0.5 + 0.5, n.z() * 0.5 + 0.5)
 }
-fn get_color_from_rgb(color: [u8; 3], intensity: f32) -> u32{
-    let mut result = (color[2] as f32*intensity) as u32;
-    result += (color[1] as f32*intensity) as u32*256;
-    result += (color[0] as f32*intensity) as u32*256*256;
-    result
+// соседние номера должны получать непохожие цвета
+fn id_color(id: usize) -> u32 {
+    let hash = (id as u32).wrapping_add(1).wrapping_mul(0x9E37_79B1);
+    (hash ^ hash >> 15) & 0xFF_FFFF
+}
+fn heat_color(count: u32) -> u32 {
+    const HEAT: [u32; 6] = [0x000000, 0x0000FF, 0x00FF00, 0xFFFF00, 0xFF8000, 0xFF0000];
+    HEAT[(count as usize).min(HEAT.len() - 1)]
+}
+
+impl PixelTarget for Scene {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+    // буфер хранится снизу вверх
+    fn get_pixel(&self, x: usize, y: usize) -> u32 {
+        self.scene[x][self.height - y - 1]
+    }
+    fn set_pixel(&mut self, x: usize, y: usize, color: u32) {
+        self.scene[x][self.height - y - 1] = color;
+    }
 }
 
 fn barycentric(a: Vector<2, f32>, b: Vector<2, f32>, c: Vector<2, f32>, p: Vector<2, f32>) -> Vector<3, f32> {
@@ -165,19 +912,34 @@ fn barycentric(a: Vector<2, f32>, b: Vector<2, f32>, c: Vector<2, f32>, p: Vecto
         s[i][1] = b[i] - a[i];
         s[i][2] = a[i] - p[i];
     }
-    let u = cross(s[0], s[1]);
+    let u = s[0] ^ s[1];
 
-    if u[2].abs() > 1e-2 {
-        Vector::new([1.0-(u[0] + u[1])/u[2], u[1]/u[2], u[0]/u[2]])
+    if u.z().abs() > 1e-2 {
+        Vector::new([1.0-(u.x() + u.y())/u.z(), u.y()/u.z(), u.x()/u.z()])
     } else {
         Vector::new([-1.0, 1.0, 1.0])
     }
 }
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::geometry::{Aabb, Frustum};
 
-fn cross(v1: Vector<3, f32>, v2: Vector<3, f32>) -> Vector<3, f32> {
-    Vector::new([
-        v1[1] * v2[2] - v1[2] * v2[1],
-        -(v1[0] * v2[2] - v1[2] * v2[0]),
-        v1[0] * v2[1] - v1[1] * v2[0]
-    ])
-}
\ No newline at end of file
+    #[test]
+    fn frame_stats_count_culling() {
+        let frustum = Frustum::from_matrix(&(Matrix::perspective(FRAC_PI_2, 1.0, 1.0, 10.0)
+            * Matrix::look_at(Vector::new([0.0, 0.0, 5.0]), Vector::empty(), Vector::new([0.0, 1.0, 0.0]))));
+        let cube = |x: f32, z: f32| Aabb::new(Vector::new([x - 0.5, -0.5, z - 0.5]), Vector::new([x + 0.5, 0.5, z + 0.5]));
+        let mut stats = FrameStats::default();
+        assert!(stats.cull_model(frustum.intersects_aabb(&cube(0.0, 0.0))));
+        // позади камеры, за дальней плоскостью, на правой границе, справа за ней
+        let chunks = [cube(0.0, 8.0), cube(0.0, -10.0), cube(5.0, 0.0), cube(8.0, 0.0)];
+        let visible: Vec<bool> = chunks.iter().map(|chunk| stats.cull_chunk(frustum.intersects_aabb(chunk))).collect();
+        assert_eq!(visible, [false, false, true, false]);
+        assert!(!stats.cull_model(frustum.intersects_aabb(&cube(0.0, 20.0))));
+        assert_eq!((stats.models_tested, stats.models_culled), (2, 1));
+        assert_eq!((stats.chunks_tested, stats.chunks_culled), (4, 3));
+    }
+}
@@ -1,2 +0,0 @@
-use crate::geometry::{Matrix, Vector};
-
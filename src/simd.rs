@@ -0,0 +1,138 @@
+// Операции над 4-компонентными f32 векторами и матрицами 4x4 для вершинной стадии.
+// С фичей `simd` считаются через wide::f32x4, без неё используются обобщённые
+// скалярные операторы из geometry - они же эталонная реализация.
+use crate::geometry::{Matrix, Vector};
+
+#[cfg(feature = "simd")]
+use wide::f32x4;
+
+#[cfg(feature = "simd")]
+fn lanes(v: Vector<4, f32>) -> f32x4 {
+    f32x4::from(v.vec)
+}
+#[cfg(feature = "simd")]
+fn vector(v: f32x4) -> Vector<4, f32> {
+    Vector::new(v.to_array())
+}
+
+pub fn add(a: Vector<4, f32>, b: Vector<4, f32>) -> Vector<4, f32> {
+    #[cfg(feature = "simd")]
+    return vector(lanes(a) + lanes(b));
+    #[cfg(not(feature = "simd"))]
+    return a + b;
+}
+
+pub fn sub(a: Vector<4, f32>, b: Vector<4, f32>) -> Vector<4, f32> {
+    #[cfg(feature = "simd")]
+    return vector(lanes(a) - lanes(b));
+    #[cfg(not(feature = "simd"))]
+    return a - b;
+}
+
+pub fn scale(a: Vector<4, f32>, k: f32) -> Vector<4, f32> {
+    #[cfg(feature = "simd")]
+    return vector(lanes(a) * f32x4::splat(k));
+    #[cfg(not(feature = "simd"))]
+    return a * k;
+}
+
+pub fn dot(a: Vector<4, f32>, b: Vector<4, f32>) -> f32 {
+    #[cfg(feature = "simd")]
+    return (lanes(a) * lanes(b)).reduce_add();
+    #[cfg(not(feature = "simd"))]
+    return a * b;
+}
+
+/// Матрица для transform: столбцы собираются в f32x4 один раз при создании, а не на каждую вершину
+#[derive(Debug, Clone)]
+pub struct SimdMatrix {
+    #[cfg(feature = "simd")]
+    cols: [f32x4; 4],
+    #[cfg(not(feature = "simd"))]
+    matrix: Matrix<4, 4>,
+}
+
+impl From<&Matrix<4, 4>> for SimdMatrix {
+    fn from(m: &Matrix<4, 4>) -> SimdMatrix {
+        #[cfg(feature = "simd")]
+        return SimdMatrix { cols: [0, 1, 2, 3].map(|j| lanes(m.col(j))) };
+        #[cfg(not(feature = "simd"))]
+        return SimdMatrix { matrix: m.clone() };
+    }
+}
+
+pub fn transform(m: &SimdMatrix, v: Vector<4, f32>) -> Vector<4, f32> {
+    #[cfg(feature = "simd")]
+    {
+        // M * v = сумма столбцов M, взвешенных компонентами v
+        let r = m.cols[0] * f32x4::splat(v[0]);
+        let r = m.cols[1].mul_add(f32x4::splat(v[1]), r);
+        let r = m.cols[2].mul_add(f32x4::splat(v[2]), r);
+        vector(m.cols[3].mul_add(f32x4::splat(v[3]), r))
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        &m.matrix * v
+    }
+}
+
+pub fn mul(a: &Matrix<4, 4>, b: &Matrix<4, 4>) -> Matrix<4, 4> {
+    #[cfg(feature = "simd")]
+    {
+        let rows = [lanes(b[0]), lanes(b[1]), lanes(b[2]), lanes(b[3])];
+        // строка i результата = сумма a[i][k] * (строка k матрицы b)
+        let row = |i: usize| {
+            let r = rows[0] * f32x4::splat(a[i][0]);
+            let r = rows[1].mul_add(f32x4::splat(a[i][1]), r);
+            let r = rows[2].mul_add(f32x4::splat(a[i][2]), r);
+            vector(rows[3].mul_add(f32x4::splat(a[i][3]), r))
+        };
+        Matrix::from_rows([row(0), row(1), row(2), row(3)])
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        a * b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    fn random_vector(rng: &mut StdRng) -> Vector<4, f32> {
+        Vector::new([(); 4].map(|_| rng.random_range(-10.0..10.0)))
+    }
+    fn random_matrix(rng: &mut StdRng) -> Matrix<4, 4> {
+        Matrix::from_rows([(); 4].map(|_| random_vector(rng)))
+    }
+    fn assert_close(a: Vector<4, f32>, b: Vector<4, f32>) {
+        for i in 0..4 {
+            // mul_add округляет один раз вместо двух
+            assert!((a[i] - b[i]).abs() <= 1e-4 * (1.0 + b[i].abs()), "{a:?} != {b:?}");
+        }
+    }
+
+    // с фичей simd сравнивает wide::f32x4 со скалярными операторами geometry, без неё проверяет их самих
+    #[test]
+    fn matches_scalar_reference() {
+        let mut rng = StdRng::seed_from_u64(33);
+        for _ in 0..1000 {
+            let (a, b) = (random_vector(&mut rng), random_vector(&mut rng));
+            let k = rng.random_range(-10.0..10.0);
+            assert_close(add(a, b), a + b);
+            assert_close(sub(a, b), a - b);
+            assert_close(scale(a, k), a * k);
+            assert!((dot(a, b) - a * b).abs() <= 1e-3 * (1.0 + (a * b).abs()));
+
+            let (m, n) = (random_matrix(&mut rng), random_matrix(&mut rng));
+            assert_close(transform(&SimdMatrix::from(&m), a), &m * a);
+            let product = mul(&m, &n);
+            let reference = &m * &n;
+            for i in 0..4 {
+                assert_close(product[i], reference[i]);
+            }
+        }
+    }
+}
@@ -1,130 +1,490 @@
-use std::{fs::File, io::{BufReader, Read}, mem, path::Path};
+use std::{error::Error, fmt, fs, io, path::Path};
 #[derive(Clone)]
 pub struct Tga {
     pub map: Vec<Vec<u32>>,
     width: usize,
-    height: usize
+    height: usize,
+    alpha: bool,
+    image_id: Vec<u8>,
+    extension: Option<TgaExtension>,
 }
 
-impl Tga {
-    pub fn read_file(path: &Path) -> Tga{
-        let mut file = BufReader::new(File::open(path).unwrap());
-
-        let mut header_bytes: [u8; HEADERSIZE] = [0; HEADERSIZE];
-
-        file.read_exact(&mut header_bytes).unwrap();
-        let header = unsafe {
-            mem::transmute::<[u8; HEADERSIZE], TgaHeader>(header_bytes)
-        };        
+#[derive(Debug, Clone)]
+pub struct TgaExtension {
+    pub author_name: String,
+    pub comments: String,
+    pub software_id: String,
+    pub key_color: u32,
+    pub pixel_aspect: Option<(u16, u16)>,
+    pub gamma: Option<f32>,
+    pub attributes_type: u8,
+}
 
+impl Tga {
+    pub fn read_file(path: &Path) -> Result<Tga, TgaError> {
+        let bytes = fs::read(path)?;
+        Tga::decode(&bytes)
+    }
+    pub fn decode(bytes: &[u8]) -> Result<Tga, TgaError> {
+        let header = TgaHeader::parse(bytes)?;
+        header.validate()?;
 
         let width = header.width as usize;
         let height = header.height as usize;
 
-        let bytespp = (header.bitsperpixel>>3) as usize;
+        let extension = Tga::read_extension(bytes);
+        let alpha = match extension.as_ref().map(|ext| ext.attributes_type) {
+            Some(0..=2) => false,
+            Some(3 | 4) => true,
+            _ => header.imagedescriptor & 0x0f > 0,
+        };
 
-        let mut buffer: Vec<u8> = Vec::with_capacity(width * height * bytespp);
+        let mut pos = HEADERSIZE;
+        let image_id = take(bytes, pos, header.idlength as usize)?.to_vec();
+        pos += header.idlength as usize;
 
-        if 3==header.datatypecode || 2==header.datatypecode{ 
-            file.read_to_end(&mut buffer).unwrap();
-        } else if 10==header.datatypecode || 11==header.datatypecode {
-            Tga::read_rle(width*height, bytespp, &mut file, &mut buffer)    
+        let mut palette = vec![];
+        if header.colormaptype == 1 {
+            let entry_bytes = (header.colormapdepth as usize).div_ceil(8);
+            let entries = take(bytes, pos, header.colormaplength as usize * entry_bytes)?;
+            for entry in entries.chunks_exact(entry_bytes) {
+                palette.push(Tga::decode_truecolor(entry, header.colormapdepth, alpha));
+            }
+            pos += entries.len();
         }
 
-        
+        let bytespp = (header.bitsperpixel as usize).div_ceil(8);
+        let pixelcount = width * height;
+
+        let buffer = match header.datatypecode {
+            1..=3 => take(bytes, pos, pixelcount*bytespp)?.to_vec(),
+            _ => Tga::read_rle(pixelcount, bytespp, bytes, pos)?,
+        };
+
+        let mut map = vec![vec![0; height]; width];
+        let right_to_left = header.imagedescriptor & 0x10 > 0;
+        let top_to_bottom = header.imagedescriptor & 0x20 > 0;
 
-        let mut map = Tga::create_canvas(width, height, bytespp, &buffer);
-    	if header.imagedescriptor&0x20 > 0 {
+        for row in 0..height {
+            for col in 0..width {
+                let offset = (row*width + col) * bytespp;
+                let pixel = &buffer[offset..offset + bytespp];
 
-            for iy in 0..height/2{
-                for item in map.iter_mut().take(width){
-                    item.swap(iy, height-1-iy);
+                let mut color = match header.datatypecode {
+                    1 | 9 => {
+                        let index = if bytespp == 1 { pixel[0] as usize } else { pixel[0] as usize + ((pixel[1] as usize) << 8) };
+                        *index.checked_sub(header.colormaporigin as usize)
+                            .and_then(|i| palette.get(i))
+                            .ok_or(TgaError::ColorMapIndex(index))?
+                    },
+                    3 | 11 => Tga::decode_grayscale(pixel, alpha),
+                    _ => Tga::decode_truecolor(pixel, header.bitsperpixel, alpha),
+                };
+                if extension.as_ref().is_some_and(|ext| ext.attributes_type == 4) {
+                    color = unpremultiply(color);
                 }
+
+                let x = if right_to_left { width - 1 - col } else { col };
+                let y = if top_to_bottom { height - 1 - row } else { row };
+                map[x][y] = color;
             }
         }
 
-        Tga {
+        Ok(Tga {
             map,
             width,
-            height
-        }
+            height,
+            alpha,
+            image_id,
+            extension,
+        })
     }
-    fn read_rle<T: Read>(pixelcount: usize, bytespp: usize, file: &mut T, encoded: &mut Vec<u8>){
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).unwrap();
+    fn read_rle(pixelcount: usize, bytespp: usize, buffer: &[u8], mut pos: usize) -> Result<Vec<u8>, TgaError> {
+        // заголовку верить нельзя: пакет из 1 + bytespp байт даёт не больше 128 пикселей
+        let available = buffer.len().saturating_sub(pos) / (1 + bytespp) * 128;
+        let mut encoded = Vec::with_capacity(pixelcount.min(available) * bytespp);
 
-        let mut pos = 0;
         let mut pix = 0;
         while pix < pixelcount {
 
-            let mut chunkheader = buffer[pos] as usize;
+            let mut chunkheader = take(buffer, pos, 1)?[0] as usize;
             pos+=1;
 
             if chunkheader<128 {
                 chunkheader+=1;
-                let endpos = pos+chunkheader*bytespp;
-                while pos < endpos {
-                    encoded.push(buffer[pos]);
-                    pos+=1;
-                }
+                encoded.extend_from_slice(take(buffer, pos, chunkheader*bytespp)?);
+                pos += chunkheader*bytespp;
             } else {
                 chunkheader -= 127;
+                let pixel = take(buffer, pos, bytespp)?;
                 for _i in 0..chunkheader{
-                    for j in 0..bytespp{
-                        encoded.push(buffer[pos+j]);
-                    }
+                    encoded.extend_from_slice(pixel);
                 }
                 pos+=bytespp;
             }
             pix+=chunkheader;
         }
+        encoded.truncate(pixelcount * bytespp);
+        Ok(encoded)
     }
+    fn read_extension(bytes: &[u8]) -> Option<TgaExtension> {
+        if bytes.len() < HEADERSIZE + FOOTERSIZE {
+            return None;
+        }
+        let footer = &bytes[bytes.len() - FOOTERSIZE..];
+        if &footer[8..] != SIGNATURE {
+            return None;
+        }
 
-    fn create_canvas(width: usize, height: usize, bytespp: usize, buffer: &Vec<u8>) -> Vec<Vec<u32>>{
-        let mut canvas = vec![vec![0;height];width];
-        for iy in 0..height{
-            for ix in 0..width{
-                if bytespp == 1 {
-                    let intensity = buffer[iy*width+ix] as u32;
-                    canvas[ix][iy] = intensity + (intensity << 8) + (intensity << 8);
-                } else if bytespp == 3 {
-                    let bytes = &buffer[(iy*width+ix)*3..(iy*width+ix+1)*3];
-                    canvas[ix][iy] = bytes[0] as u32 + ((bytes[1] as u32) << 8) + ((bytes[2] as u32) << (8*2));
-                } else if bytespp == 4 {
-                    let bytes = &buffer[(iy*width+ix)*4..(iy*width+ix+1)*4];
-                    canvas[ix][iy] = bytes[0] as u32 + ((bytes[1] as u32) << 8) + ((bytes[2] as u32) << (8*2));
-                }
-            }
+        let offset = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]) as usize;
+        if offset == 0 || offset + EXTENSIONSIZE > bytes.len() - FOOTERSIZE {
+            return None;
+        }
+        let ext = &bytes[offset..offset + EXTENSIONSIZE];
+        // первое поле - размер области, для TGA 2.0 всегда 495
+        if u16::from_le_bytes([ext[0], ext[1]]) as usize != EXTENSIONSIZE {
+            return None;
+        }
+
+        let text = |from: usize, len: usize| {
+            let field = &ext[from..from + len];
+            let end = field.iter().position(|&b| b == 0).unwrap_or(len);
+            String::from_utf8_lossy(&field[..end]).trim_end().to_string()
+        };
+        let word = |at: usize| u16::from_le_bytes([ext[at], ext[at + 1]]);
+
+        let pixel_aspect = (word(476) != 0).then(|| (word(474), word(476)));
+        let gamma = (word(480) != 0).then(|| word(478) as f32 / word(480) as f32);
+
+        Some(TgaExtension {
+            author_name: text(2, 41),
+            comments: text(43, 324),
+            software_id: text(426, 41),
+            key_color: u32::from_le_bytes([ext[470], ext[471], ext[472], ext[473]]),
+            pixel_aspect,
+            gamma,
+            attributes_type: ext[494],
+        })
+    }
+
+    fn decode_truecolor(bytes: &[u8], bitsperpixel: u8, alpha: bool) -> u32 {
+        match bitsperpixel {
+            15 | 16 => {
+                let v = bytes[0] as u32 + ((bytes[1] as u32) << 8);
+                let expand = |c: u32| (c << 3) | (c >> 2);
+                let a = if bitsperpixel == 16 && alpha && v & 0x8000 == 0 { 0 } else { 0xff };
+                (a << 24) + (expand((v >> 10) & 0x1f) << 16) + (expand((v >> 5) & 0x1f) << 8) + expand(v & 0x1f)
+            },
+            24 => 0xff00_0000 + bytes[0] as u32 + ((bytes[1] as u32) << 8) + ((bytes[2] as u32) << (8*2)),
+            32 => {
+                let a = if alpha { bytes[3] as u32 } else { 0xff };
+                (a << (8*3)) + bytes[0] as u32 + ((bytes[1] as u32) << 8) + ((bytes[2] as u32) << (8*2))
+            },
+            _ => unreachable!("pixel depth is checked by TgaHeader::validate"),
         }
-        canvas
+    }
+    fn decode_grayscale(bytes: &[u8], alpha: bool) -> u32 {
+        let intensity = bytes[0] as u32;
+        let a = if bytes.len() > 1 && alpha { bytes[1] as u32 } else { 0xff };
+        (a << (8*3)) + intensity + (intensity << 8) + (intensity << (8*2))
     }
     pub fn get_pixel(&self, x: i32, y: i32) -> u32 {
         self.map[x as usize][y as usize]
     }
-    
+
     pub fn width(&self) -> usize {
         self.width
     }
     pub fn height(&self) -> usize {
         self.height
     }
+    pub fn has_alpha(&self) -> bool {
+        self.alpha
+    }
+    pub fn image_id(&self) -> &[u8] {
+        &self.image_id
+    }
+    pub fn extension(&self) -> Option<&TgaExtension> {
+        self.extension.as_ref()
+    }
+}
+
+fn unpremultiply(color: u32) -> u32 {
+    let a = color >> (8*3);
+    if a == 0 || a == 0xff {
+        return color;
+    }
+    let channel = |shift: u32| (((color >> shift) & 0xff) * 0xff / a).min(0xff) << shift;
+    (a << (8*3)) + channel(8*2) + channel(8) + channel(0)
 }
 
 const HEADERSIZE: usize = 18;
+const MAXPIXELS: usize = 1 << 26;
+const FOOTERSIZE: usize = 26;
+const EXTENSIONSIZE: usize = 495;
+const SIGNATURE: &[u8] = b"TRUEVISION-XFILE.\0";
+
+#[derive(Debug)]
+pub enum TgaError {
+    Io(io::Error),
+    Truncated { offset: usize, needed: usize, available: usize },
+    UnsupportedImageType(u8),
+    UnsupportedPixelDepth { datatypecode: u8, bitsperpixel: u8 },
+    InvalidColorMap { colormaptype: u8, colormapdepth: u8, colormaplength: u16 },
+    InvalidDimensions { width: u16, height: u16 },
+    ColorMapIndex(usize),
+}
+
+impl fmt::Display for TgaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TgaError::Io(err) => write!(f, "failed to read TGA file: {}", err),
+            TgaError::Truncated { offset, needed, available } =>
+                write!(f, "truncated TGA data: {} bytes needed at offset {}, {} available", needed, offset, available),
+            TgaError::UnsupportedImageType(code) => write!(f, "unsupported TGA image type {}", code),
+            TgaError::UnsupportedPixelDepth { datatypecode, bitsperpixel } =>
+                write!(f, "unsupported pixel depth {} for TGA image type {}", bitsperpixel, datatypecode),
+            TgaError::InvalidColorMap { colormaptype, colormapdepth, colormaplength } =>
+                write!(f, "invalid TGA color map (type {}, depth {}, length {})", colormaptype, colormapdepth, colormaplength),
+            TgaError::InvalidDimensions { width, height } => write!(f, "invalid TGA dimensions {}x{}", width, height),
+            TgaError::ColorMapIndex(index) => write!(f, "TGA color map index {} out of range", index),
+        }
+    }
+}
+
+impl Error for TgaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TgaError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TgaError {
+    fn from(err: io::Error) -> TgaError {
+        TgaError::Io(err)
+    }
+}
+
+fn take(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8], TgaError> {
+    offset.checked_add(len)
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or(TgaError::Truncated { offset, needed: len, available: bytes.len().saturating_sub(offset) })
+}
 
 #[derive(Debug)]
-#[repr(C, packed)]
 struct TgaHeader {
-	idlength: i8,
-	colormaptype: i8,
-	datatypecode: i8,
-	colormaporigin: i16,
-	colormaplength: i16,
-	colormapdepth: i8,
-	x_origin: i16,
-	y_origin: i16,
-	width: i16,
-	height: i16,
-	bitsperpixel: i8,
-	imagedescriptor: i8,
-}
\ No newline at end of file
+	idlength: u8,
+	colormaptype: u8,
+	datatypecode: u8,
+	colormaporigin: u16,
+	colormaplength: u16,
+	colormapdepth: u8,
+	width: u16,
+	height: u16,
+	bitsperpixel: u8,
+	imagedescriptor: u8,
+}
+
+impl TgaHeader {
+    fn parse(bytes: &[u8]) -> Result<TgaHeader, TgaError> {
+        let h = take(bytes, 0, HEADERSIZE)?;
+        let word = |at: usize| u16::from_le_bytes([h[at], h[at + 1]]);
+
+        Ok(TgaHeader {
+            idlength: h[0],
+            colormaptype: h[1],
+            datatypecode: h[2],
+            colormaporigin: word(3),
+            colormaplength: word(5),
+            colormapdepth: h[7],
+            // x и y origin (байты 8-11) - положение на экране, декодированию не нужны
+            width: word(12),
+            height: word(14),
+            bitsperpixel: h[16],
+            imagedescriptor: h[17],
+        })
+    }
+    fn validate(&self) -> Result<(), TgaError> {
+        let depth_ok = match self.datatypecode {
+            1 | 9 => matches!(self.bitsperpixel, 8 | 16),
+            2 | 10 => matches!(self.bitsperpixel, 15 | 16 | 24 | 32),
+            3 | 11 => matches!(self.bitsperpixel, 8 | 16),
+            code => return Err(TgaError::UnsupportedImageType(code)),
+        };
+        if !depth_ok {
+            return Err(TgaError::UnsupportedPixelDepth { datatypecode: self.datatypecode, bitsperpixel: self.bitsperpixel });
+        }
+
+        let color_mapped = matches!(self.datatypecode, 1 | 9);
+        let colormap_ok = match self.colormaptype {
+            0 => !color_mapped,
+            1 => matches!(self.colormapdepth, 15 | 16 | 24 | 32) && (!color_mapped || self.colormaplength > 0),
+            _ => false,
+        };
+        if !colormap_ok {
+            return Err(TgaError::InvalidColorMap {
+                colormaptype: self.colormaptype,
+                colormapdepth: self.colormapdepth,
+                colormaplength: self.colormaplength,
+            });
+        }
+
+        if self.width == 0 || self.height == 0 || self.width as usize * self.height as usize > MAXPIXELS {
+            return Err(TgaError::InvalidDimensions { width: self.width, height: self.height });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: u32 = 0xFFFF_0000;
+    const GREEN: u32 = 0xFF00_FF00;
+    const BLUE: u32 = 0xFF00_00FF;
+    const WHITE: u32 = 0xFFFF_FFFF;
+    const BLACK: u32 = 0xFF00_0000;
+    // картинка всех фикстур 4x2, снизу вверх
+    const IMAGE: [[u32; 4]; 2] = [[RED, RED, GREEN, BLUE], [WHITE, WHITE, WHITE, BLACK]];
+
+    fn fixture(name: &str) -> Tga {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/tga").join(name);
+        Tga::read_file(&path).unwrap()
+    }
+    fn assert_pixels(tga: &Tga, expected: [[u32; 4]; 2]) {
+        assert_eq!((tga.width(), tga.height()), (4, 2));
+        for (y, row) in expected.iter().enumerate() {
+            for (x, &color) in row.iter().enumerate() {
+                assert_eq!(tga.get_pixel(x as i32, y as i32), color, "pixel ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn color_mapped() {
+        for name in ["colormap8.tga", "colormap16.tga", "rle_colormap8.tga", "rle_colormap16.tga"] {
+            let tga = fixture(name);
+            assert_pixels(&tga, IMAGE);
+            assert!(!tga.has_alpha(), "{name}");
+        }
+    }
+
+    #[test]
+    fn rgb15() {
+        assert_pixels(&fixture("rgb15.tga"), IMAGE);
+    }
+
+    #[test]
+    fn argb16() {
+        let tga = fixture("argb16.tga");
+        assert!(tga.has_alpha());
+        let mut expected = IMAGE;
+        expected[1][0] = 0x00FF_FFFF;
+        assert_pixels(&tga, expected);
+    }
+
+    #[test]
+    fn rgba32() {
+        let mut expected = IMAGE;
+        expected[0][3] = 0x8000_00FF;
+        for name in ["rgba32.tga", "rle_rgba32.tga"] {
+            let tga = fixture(name);
+            assert!(tga.has_alpha(), "{name}");
+            assert_pixels(&tga, expected);
+        }
+    }
+
+    #[test]
+    fn image_id() {
+        let tga = fixture("image_id.tga");
+        assert_eq!(tga.image_id(), b"hello");
+        assert_pixels(&tga, IMAGE);
+        assert!(fixture("rgb15.tga").image_id().is_empty());
+    }
+
+    #[test]
+    fn origins() {
+        assert_pixels(&fixture("right_to_left.tga"), IMAGE);
+        assert_pixels(&fixture("top_to_bottom.tga"), IMAGE);
+    }
+
+    #[test]
+    fn extension_area() {
+        let tga = fixture("premultiplied.tga");
+        let ext = tga.extension().unwrap();
+        assert_eq!(ext.author_name, "Tester");
+        assert_eq!(ext.comments, "");
+        assert_eq!(ext.software_id, "test");
+        assert_eq!(ext.key_color, 0xFF10_2030);
+        assert_eq!(ext.pixel_aspect, Some((4, 3)));
+        assert_eq!(ext.gamma, Some(2.2));
+        assert_eq!(ext.attributes_type, 4);
+        assert!(fixture("rgba32.tga").extension().is_none());
+    }
+
+    #[test]
+    fn extension_size_must_be_495() {
+        let mut bytes = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/tga/premultiplied.tga")).unwrap();
+        let footer = bytes.len() - FOOTERSIZE;
+        let offset = u32::from_le_bytes(bytes[footer..footer + 4].try_into().unwrap()) as usize;
+        assert_eq!(&bytes[offset..offset + 2], &495u16.to_le_bytes());
+        for size in [0u16, 494, 496, 0xffff] {
+            bytes[offset..offset + 2].copy_from_slice(&size.to_le_bytes());
+            let tga = Tga::decode(&bytes).unwrap();
+            assert!(tga.extension().is_none(), "size {size}");
+            // без расширения альфа не домножена и берётся из дескриптора
+            assert_eq!(tga.get_pixel(3, 0), 0x8040_2010);
+        }
+    }
+
+    #[test]
+    fn huge_rle_header_with_no_data() {
+        // 65535x1024 пикселей RLE без единого пакета: ошибка, а не попытка выделить гигабайты
+        let mut bytes = vec![0u8; HEADERSIZE];
+        bytes[2] = 10;
+        bytes[12..14].copy_from_slice(&0xffffu16.to_le_bytes());
+        bytes[14..16].copy_from_slice(&1024u16.to_le_bytes());
+        bytes[16] = 32;
+        assert!(matches!(Tga::decode(&bytes), Err(TgaError::Truncated { .. })));
+        assert_eq!(Tga::read_rle(1 << 26, 4, &[0x80, 1, 2, 3, 4], 0).unwrap_err().to_string(),
+            "truncated TGA data: 1 bytes needed at offset 5, 0 available");
+    }
+
+    #[test]
+    fn premultiplied_alpha() {
+        let tga = fixture("premultiplied.tga");
+        assert!(tga.has_alpha());
+        let mut expected = IMAGE;
+        // 0x40 * 255 / 0x80 = 0x7F и т.д.
+        expected[0][3] = 0x807F_3F1F;
+        assert_pixels(&tga, expected);
+    }
+
+    #[test]
+    fn alpha_ignored_by_attributes_type() {
+        let tga = fixture("alpha_ignored.tga");
+        assert!(!tga.has_alpha());
+        let mut expected = IMAGE;
+        expected[0][3] = 0xFF40_2010;
+        assert_pixels(&tga, expected);
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/tga/rle_colormap16.tga");
+        let bytes = fs::read(path).unwrap();
+        for len in 0..bytes.len() {
+            assert!(Tga::decode(&bytes[..len]).is_err(), "{len} bytes");
+        }
+        let mut bad_type = bytes.clone();
+        bad_type[2] = 5;
+        assert!(matches!(Tga::decode(&bad_type), Err(TgaError::UnsupportedImageType(5))));
+        let mut bad_index = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/tga/colormap8.tga")).unwrap();
+        *bad_index.last_mut().unwrap() = 200;
+        assert!(matches!(Tga::decode(&bad_index), Err(TgaError::ColorMapIndex(200))));
+        assert!(matches!(Tga::read_file(Path::new("missing.tga")), Err(TgaError::Io(_))));
+    }
+}